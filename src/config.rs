@@ -0,0 +1,229 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io};
+use tgt_warmup::kucoin::{Backoff, BookDepth};
+use thiserror::Error;
+
+/// Settings of the capture, as loaded from a TOML file. Every setting may
+/// be left out, and flags given on the command line take precedence.
+///
+/// ```toml
+/// symbols = ["ETHUSDTM", "XBTUSDTM"]
+/// topics = ["level2", "trades"]
+/// depth = 50
+///
+/// [api]
+/// key = "..."
+/// secret = "..."
+/// passphrase = "..."
+///
+/// [reconnect]
+/// initial_ms = 500
+/// max_attempts = 10
+///
+/// [output]
+/// path = "csv:books.csv"
+/// flush_ms = 5000
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    #[serde(default)]
+    pub topics: Vec<CaptureTopic>,
+    /// Levels of each side of level 2 books, 5 or 50.
+    depth: Option<u32>,
+    /// Key to connect with, for a private token. Read from the environment
+    /// unless set, see `Credentials::from_env`.
+    pub api: Option<ApiConfig>,
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    /// Seconds to capture for.
+    pub duration: Option<u64>,
+}
+
+/// Topic of the symbols to capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureTopic {
+    /// Level 2 books
+    Level2,
+    /// Best bid and ask
+    Ticker,
+    /// Trades
+    Trades,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiConfig {
+    pub key: String,
+    pub secret: String,
+    pub passphrase: String,
+}
+
+/// How to reconnect once the connection drops, see `Backoff`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReconnectConfig {
+    /// Whether to reconnect at all.
+    pub enabled: bool,
+    pub initial_ms: u64,
+    pub max_ms: u64,
+    pub multiplier: f64,
+    pub jitter: f64,
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        let backoff = Backoff::default();
+        ReconnectConfig {
+            enabled: true,
+            initial_ms: backoff.initial.as_millis() as u64,
+            max_ms: backoff.max.as_millis() as u64,
+            multiplier: backoff.multiplier,
+            jitter: backoff.jitter,
+            max_attempts: backoff.max_attempts,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// The backoff to reconnect with, `None` if reconnecting is disabled.
+    pub fn backoff(&self) -> Option<Backoff> {
+        self.enabled.then(|| Backoff {
+            initial: Duration::from_millis(self.initial_ms),
+            max: Duration::from_millis(self.max_ms),
+            multiplier: self.multiplier,
+            jitter: self.jitter,
+            max_attempts: self.max_attempts,
+        })
+    }
+}
+
+/// Where captured messages go.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// File to write to, see `parse_output`. Stdout unless set.
+    path: Option<String>,
+    /// Milliseconds between flushes of CSV output.
+    pub flush_ms: Option<u64>,
+}
+
+impl OutputConfig {
+    /// Where to write to, if set.
+    pub fn target(&self) -> Option<OutputTarget> {
+        self.path
+            .as_deref()
+            .and_then(|path| parse_output(path).ok())
+    }
+}
+
+/// File to write captured messages to.
+#[derive(Debug, Clone)]
+pub enum OutputTarget {
+    /// Every message as a line of JSON
+    Json(PathBuf),
+    /// Level 2 books as rows of CSV, see `CsvRecorder`
+    Csv(PathBuf),
+}
+
+/// Failure to load a config file.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("cannot read config {}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("invalid config {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: Box<toml::de::Error>,
+    },
+    #[error("invalid config {}: {reason}", path.display())]
+    Invalid { path: PathBuf, reason: String },
+}
+
+impl Config {
+    /// Reads and validates the config file at `path`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        let config: Config = toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_owned(),
+            source: Box::new(source),
+        })?;
+
+        config.validate().map_err(|reason| ConfigError::Invalid {
+            path: path.to_owned(),
+            reason,
+        })?;
+        Ok(config)
+    }
+
+    /// Levels of each side of level 2 books, if set.
+    pub fn depth(&self) -> Option<BookDepth> {
+        self.depth
+            .and_then(|depth| parse_depth(&depth.to_string()).ok())
+    }
+
+    // Checks what the types of the fields cannot
+    fn validate(&self) -> Result<(), String> {
+        if let Some(depth) = self.depth {
+            parse_depth(&depth.to_string())?;
+        }
+        if let Some(path) = &self.output.path {
+            parse_output(path).map_err(|reason| format!("output.path: {reason}"))?;
+        }
+        if let Some(symbol) = self.symbols.iter().find(|symbol| symbol.trim().is_empty()) {
+            return Err(format!("symbol {symbol:?} is empty"));
+        }
+
+        let reconnect = &self.reconnect;
+        if reconnect.initial_ms > reconnect.max_ms {
+            return Err(format!(
+                "reconnect.initial_ms ({}) is above reconnect.max_ms ({})",
+                reconnect.initial_ms, reconnect.max_ms
+            ));
+        }
+        if reconnect.multiplier < 1.0 {
+            return Err(format!(
+                "reconnect.multiplier must be at least 1, not {}",
+                reconnect.multiplier
+            ));
+        }
+        if !(0.0..=1.0).contains(&reconnect.jitter) {
+            return Err(format!(
+                "reconnect.jitter must be within 0 and 1, not {}",
+                reconnect.jitter
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parses the depth of level 2 books, 5 or 50.
+pub fn parse_depth(depth: &str) -> Result<BookDepth, String> {
+    match depth {
+        "5" => Ok(BookDepth::Five),
+        "50" => Ok(BookDepth::Fifty),
+        _ => Err(format!("depth must be 5 or 50, not {depth}")),
+    }
+}
+
+/// Parses the file to write to: `csv:FILE` for CSV, any other path for JSON
+/// lines.
+pub fn parse_output(output: &str) -> Result<OutputTarget, String> {
+    match output.strip_prefix("csv:") {
+        Some("") => Err("csv output needs a file, e.g. csv:books.csv".to_owned()),
+        Some(path) => Ok(OutputTarget::Csv(path.into())),
+        None => Ok(OutputTarget::Json(output.into())),
+    }
+}
@@ -1,9 +1,67 @@
+mod account;
 mod api;
+#[cfg(feature = "async")]
+mod async_session;
+mod book;
+mod book_sync;
+mod builder;
 mod client;
+mod clock;
+mod contract;
+#[cfg(feature = "decimal")]
+mod decimal;
 mod error;
-mod book;
+mod event;
+mod instrument;
+mod json;
+mod kline;
+mod level3;
+mod orders;
+mod pool;
+mod rate_limit;
+mod reconnect;
+mod rest;
+mod sequence;
+mod signals;
+mod signing;
+mod spot;
+mod subscription;
+mod ticker;
+mod token;
+mod topic;
+mod trade;
 
+pub use account::{AccountOverview, Position, PositionUpdate, WalletUpdate};
+pub use api::{Credentials, CredentialsConfig, MarketType};
+#[cfg(feature = "async")]
+pub use async_session::AsyncSession;
+pub use book::{BookDepth, BookSummary, Level, MarketBook, NormalizedBook, OrderBook, Price, Side};
+pub use builder::SessionBuilder;
 pub use client::Session;
-pub use api::Credentials;
-pub use error::RecvError;
-pub use book::MarketBook;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use contract::ContractInfo;
+#[cfg(feature = "decimal")]
+pub use decimal::DecimalLevel;
+pub use error::{KucoinError, RecvError};
+pub use event::SessionEvent;
+pub use instrument::{
+    Announcement, FundingRate, FundingSettlement, IndexPrice, InstrumentEvent, MarkPrice,
+};
+pub use kline::{Candle, KlineInterval};
+pub use level3::{DoneReason, Level3Book, Level3Event, RestingOrder};
+pub use orders::{
+    CanceledClientOrder, CanceledOrders, Fill, FillQuery, Liquidity, Order, OrderEventKind,
+    OrderRequest, OrderType, OrderUpdate, PlacedOrder, StopDirection, StopOrderEventKind,
+    StopOrderUpdate, TimeInForce,
+};
+pub use pool::{SessionPool, MAX_TOPICS_PER_SESSION};
+pub use reconnect::{Backoff, ReconnectEvent, RECONNECT_HISTORY_LEN};
+pub use rest::{Page, Paginated, RestClient};
+pub use sequence::{FeedEvent, SEQUENCE_RESET_MAX};
+pub use signals::{Signal, SignalTracker, Signals};
+pub use spot::{SpotBookChanges, SpotLevelChange, SpotTicker, SpotTrade};
+pub use subscription::{CrossedBooks, Subscription, SubscriptionOptions};
+pub use ticker::{MarketSnapshot, Ticker};
+pub use token::{ApiTokenProvider, TokenProvider};
+pub use topic::Topic;
+pub use trade::Trade;
@@ -0,0 +1,184 @@
+use super::error::RecvError;
+use super::json::de;
+use serde::Deserialize;
+
+/// A change to one of the account's futures positions, as sent on the
+/// private `/contract/position:{symbol}` topic.
+///
+/// Mark price changes only carry the fields that depend on the mark price,
+/// leaving the others `None`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionUpdate {
+    // Mark price changes leave the symbol out of the data, see `from_raw`
+    #[serde(default)]
+    pub symbol: String,
+    /// Size of the position in contracts, negative when short.
+    #[serde(rename = "currentQty", default, deserialize_with = "de::optional_size")]
+    pub qty: Option<i64>,
+    #[serde(
+        rename = "avgEntryPrice",
+        default,
+        deserialize_with = "de::optional_float"
+    )]
+    pub entry_price: Option<f64>,
+    #[serde(default, deserialize_with = "de::optional_float")]
+    pub liquidation_price: Option<f64>,
+    #[serde(default, deserialize_with = "de::optional_float")]
+    pub mark_price: Option<f64>,
+    /// Margin held by the position, in the settlement currency.
+    #[serde(rename = "posMargin", default, deserialize_with = "de::optional_float")]
+    pub margin: Option<f64>,
+    #[serde(default, deserialize_with = "de::optional_float")]
+    pub unrealised_pnl: Option<f64>,
+    #[serde(default, deserialize_with = "de::optional_float")]
+    pub realised_pnl: Option<f64>,
+    /// When the position changed, in milliseconds since the epoch.
+    #[serde(
+        rename = "currentTimestamp",
+        default,
+        deserialize_with = "de::optional_integer"
+    )]
+    pub ts: Option<u64>,
+}
+
+impl PositionUpdate {
+    /// Parses the data message of a position topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        Ok(PositionUpdate::from_raw(RawPositionUpdate::deserialize(
+            msg,
+        )?))
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        Ok(PositionUpdate::from_raw(serde_json::from_str(text)?))
+    }
+
+    fn from_raw(raw: RawPositionUpdate) -> Self {
+        let mut update = raw.data;
+        if update.symbol.is_empty() {
+            update.symbol = match raw.topic.split_once(':') {
+                Some((_, symbol)) => symbol.to_owned(),
+                None => raw.topic,
+            };
+        }
+        update
+    }
+}
+
+// A message of a position topic, whose topic names the symbol
+#[derive(Deserialize)]
+struct RawPositionUpdate {
+    topic: String,
+    data: PositionUpdate,
+}
+
+/// A change to the account's futures wallet in one currency, as sent on the
+/// private `/contractAccount/wallet` topic.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "subject", content = "data")]
+pub enum WalletUpdate {
+    /// Funds free to open positions with, and funds held by open orders.
+    #[serde(rename = "availableBalance.change", rename_all = "camelCase")]
+    AvailableBalance {
+        currency: String,
+        #[serde(deserialize_with = "de::float")]
+        available_balance: f64,
+        #[serde(deserialize_with = "de::float")]
+        hold_balance: f64,
+        #[serde(rename = "timestamp", deserialize_with = "de::integer")]
+        ts: u64,
+    },
+    /// Margin held by open orders.
+    #[serde(rename = "orderMargin.change", rename_all = "camelCase")]
+    OrderMargin {
+        currency: String,
+        #[serde(deserialize_with = "de::float")]
+        order_margin: f64,
+        #[serde(rename = "timestamp", deserialize_with = "de::integer")]
+        ts: u64,
+    },
+    /// Funds held by pending withdrawals.
+    #[serde(rename = "withdrawHold.change", rename_all = "camelCase")]
+    WithdrawHold {
+        currency: String,
+        #[serde(deserialize_with = "de::float")]
+        withdraw_hold: f64,
+        #[serde(rename = "timestamp", deserialize_with = "de::integer")]
+        ts: u64,
+    },
+}
+
+impl WalletUpdate {
+    /// Parses the data message of the wallet topic. Timestamps are in
+    /// milliseconds since the epoch.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        Ok(WalletUpdate::deserialize(msg)?)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        Ok(serde_json::from_str(text)?)
+    }
+}
+
+/// The account's futures balances in one currency, as returned by KuCoin's
+/// account overview API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountOverview {
+    pub currency: String,
+    /// Margin balance plus unrealised PnL.
+    #[serde(deserialize_with = "de::float")]
+    pub account_equity: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub margin_balance: f64,
+    /// Funds free to open positions with.
+    #[serde(deserialize_with = "de::float")]
+    pub available_balance: f64,
+    /// Margin held by open positions.
+    #[serde(deserialize_with = "de::float")]
+    pub position_margin: f64,
+    /// Margin held by open orders.
+    #[serde(deserialize_with = "de::float")]
+    pub order_margin: f64,
+    /// Funds held by pending withdrawals and transfers.
+    #[serde(deserialize_with = "de::float")]
+    pub frozen_funds: f64,
+    #[serde(rename = "unrealisedPNL", deserialize_with = "de::float")]
+    pub unrealised_pnl: f64,
+}
+
+/// One of the account's futures positions, as listed by KuCoin's positions
+/// API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Position {
+    pub symbol: String,
+    /// Size of the position in contracts, negative when short.
+    #[serde(rename = "currentQty", deserialize_with = "de::size")]
+    pub qty: i64,
+    #[serde(rename = "avgEntryPrice", deserialize_with = "de::float")]
+    pub entry_price: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub liquidation_price: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub mark_price: f64,
+    #[serde(rename = "realLeverage", deserialize_with = "de::float")]
+    pub leverage: f64,
+    /// Margin held by the position, in the settlement currency.
+    #[serde(rename = "posMargin", deserialize_with = "de::float")]
+    pub margin: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub unrealised_pnl: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub realised_pnl: f64,
+    pub settle_currency: String,
+    /// Whether the position is open.
+    #[serde(default)]
+    pub is_open: bool,
+    /// When the position was last updated, in milliseconds since the epoch.
+    #[serde(rename = "currentTimestamp", deserialize_with = "de::integer")]
+    pub ts: u64,
+}
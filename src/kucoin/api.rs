@@ -1,59 +1,297 @@
+use super::book::{BookDepth, OrderBook};
+use super::client;
+use super::error::{KucoinError, RecvError};
+use super::rest::RestClient;
+use super::signing::ApiKey;
+use super::topic::Topic;
+use serde::Deserialize;
 use serde_json::json;
-use std::{error::Error, time::Duration};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+use tungstenite::protocol::WebSocketConfig;
 
-const DEFAULT_API_DOMAIN: &str = "https://api.kucoin.com";
+const SPOT_API_DOMAIN: &str = "https://api.kucoin.com";
 const DEFAULT_TOKEN_ENDPOINT: &str = "/api/v1/bullet-public";
+const PRIVATE_TOKEN_ENDPOINT: &str = "/api/v1/bullet-private";
+pub(super) const FUTURES_API_DOMAIN: &str = "https://api-futures.kucoin.com";
+const LEVEL2_SNAPSHOT_ENDPOINT: &str = "/api/v1/level2/snapshot";
+// Values KuCoin currently hands out with its tokens
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_millis(18000);
+const DEFAULT_PING_TIMEOUT: Duration = Duration::from_millis(10000);
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 << 20;
+const DEFAULT_MAX_FRAME_SIZE: usize = 1 << 20;
+// Environment variables read by `Credentials::from_env`
+const KEY_VAR: &str = "KUCOIN_API_KEY";
+const SECRET_VAR: &str = "KUCOIN_API_SECRET";
+const PASSPHRASE_VAR: &str = "KUCOIN_API_PASSPHRASE";
+const DOMAIN_VAR: &str = "KUCOIN_API_DOMAIN";
+const PROXY_VAR: &str = "KUCOIN_PROXY";
+// KuCoin accepts at most this many symbols in a single subscribe frame.
+pub const MAX_SYMBOLS_PER_FRAME: usize = 100;
 
-#[derive(Debug)]
+/// The KuCoin market a token gives access to. Spot and futures topics are
+/// served by different instance servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MarketType {
+    Spot,
+    #[default]
+    Futures,
+}
+
+impl MarketType {
+    // Domain of the REST API handing out tokens for the market
+    fn api_domain(self) -> &'static str {
+        match self {
+            MarketType::Spot => SPOT_API_DOMAIN,
+            MarketType::Futures => FUTURES_API_DOMAIN,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Credentials {
     wss_domain: String,
     token: String,
 
     pub ping_interval: Duration,
     pub ping_timeout: Duration,
+
+    /// Largest message accepted from the server, in bytes.
+    pub max_message_size: usize,
+    /// Largest single frame accepted from the server, in bytes.
+    pub max_frame_size: usize,
+
+    /// Query parameters appended to the connection string after the token
+    /// and `connectId`, e.g. `("acceptUserMessage", "true")`. Values are
+    /// sent as is, so they must already be URL-encoded.
+    pub extra_params: Vec<(String, String)>,
+
+    /// Market the token is for; `refresh` fetches a new token for the same
+    /// market. Futures unless set.
+    pub market: MarketType,
+
+    // Signs token requests of private credentials, so they can be refreshed
+    api_key: Option<ApiKey>,
+    // Where tokens are fetched from, if not the default domain of `market`
+    api_domain: Option<String>,
+    // Proxy of token requests, if any
+    proxy: Option<String>,
+    // Instance server connected to instead of the one handed out with the
+    // token, if configured
+    endpoint_override: Option<String>,
 }
 
 impl Credentials {
     /// Constructs a Credentials for connecting with KuCoin's WebSocket API.
     /// Automatically fetches token from KuCoin's API.
-    pub fn new_with_token() -> Result<Self, Box<dyn Error>> {
-        let url = format!("{DEFAULT_API_DOMAIN}{DEFAULT_TOKEN_ENDPOINT}");
+    pub fn new_with_token() -> Result<Self, KucoinError> {
+        Self::new_with_token_for(MarketType::Futures)
+    }
+
+    /// Same as `new_with_token`, for the topics of `market`, e.g. to
+    /// subscribe to spot topics with `Session::subscribe_spot_level2`.
+    pub fn new_with_token_for(market: MarketType) -> Result<Self, KucoinError> {
+        Self::fetch(market, None, None, None)
+    }
+
+    /// Constructs a Credentials for connecting with KuCoin's WebSocket API
+    /// with access to private topics, e.g. `/contractMarket/tradeOrders`.
+    /// Fetches a private token from KuCoin's API, signing the request with
+    /// the API `key`, `secret` and `passphrase`.
+    ///
+    /// Subscribe to private topics with `Session::subscribe_private`.
+    pub fn new_private(key: &str, secret: &str, passphrase: &str) -> Result<Self, KucoinError> {
+        Self::new_private_for(MarketType::Futures, key, secret, passphrase)
+    }
+
+    /// Same as `new_private`, for the private topics of `market`, e.g. spot
+    /// orders.
+    pub fn new_private_for(
+        market: MarketType,
+        key: &str,
+        secret: &str,
+        passphrase: &str,
+    ) -> Result<Self, KucoinError> {
+        Self::fetch(
+            market,
+            Some(ApiKey::new(key, secret, passphrase)),
+            None,
+            None,
+        )
+    }
 
-        let client = reqwest::blocking::Client::new();
-        let resp: serde_json::Value = client.post(url).send()?.error_for_status()?.json()?;
+    /// Same as `new_with_token`, or `new_private` if an API key is set,
+    /// configured through environment variables so that secrets stay out of
+    /// code and config files:
+    /// - `KUCOIN_API_KEY`, `KUCOIN_API_SECRET` and `KUCOIN_API_PASSPHRASE`:
+    ///   the API key for a private token, all or none of them
+    /// - `KUCOIN_API_DOMAIN`: where to fetch tokens from, e.g. a sandbox
+    /// - `KUCOIN_PROXY`: proxy URL of token requests, e.g.
+    ///   `socks5://127.0.0.1:1080`. The WebSocket connection does not go
+    ///   through it.
+    ///
+    /// Unset or empty variables are left at their defaults.
+    pub fn from_env() -> Result<Self, KucoinError> {
+        Self::from_env_for(MarketType::Futures)
+    }
+
+    /// Same as `from_env`, for the topics of `market`.
+    pub fn from_env_for(market: MarketType) -> Result<Self, KucoinError> {
+        let var = |name: &'static str| env::var(name).ok().filter(|value| !value.is_empty());
+
+        let parts = [KEY_VAR, SECRET_VAR, PASSPHRASE_VAR].map(|name| (name, var(name)));
+        let api_key = match &parts {
+            [(_, Some(key)), (_, Some(secret)), (_, Some(passphrase))] => {
+                Some(ApiKey::new(key, secret, passphrase))
+            }
+            [(_, None), (_, None), (_, None)] => None,
+            _ => {
+                let (name, _) = parts.iter().find(|(_, value)| value.is_none()).unwrap();
+                return Err(KucoinError::Env {
+                    name,
+                    reason: "is not set, but the other parts of the API key are".to_owned(),
+                });
+            }
+        };
+
+        Self::fetch(market, api_key, var(DOMAIN_VAR), var(PROXY_VAR))
+    }
+
+    // Fetches a token for `market` from `api_domain`, or the market's own
+    // domain, signing the request with `api_key` for a private token
+    fn fetch(
+        market: MarketType,
+        api_key: Option<ApiKey>,
+        api_domain: Option<String>,
+        proxy: Option<String>,
+    ) -> Result<Self, KucoinError> {
+        let domain = api_domain.as_deref().unwrap_or(market.api_domain());
+        let endpoint = match api_key {
+            Some(_) => PRIVATE_TOKEN_ENDPOINT,
+            None => DEFAULT_TOKEN_ENDPOINT,
+        };
+
+        let mut client = reqwest::blocking::Client::builder().use_native_tls();
+        if let Some(proxy) = &proxy {
+            let proxy = reqwest::Proxy::all(proxy).map_err(|e| KucoinError::Env {
+                name: PROXY_VAR,
+                reason: format!("is not a proxy URL: {e}"),
+            })?;
+            client = client.proxy(proxy);
+        }
+        let client = client.build().map_err(KucoinError::TlsUnavailable)?;
+
+        let mut request = client.post(format!("{domain}{endpoint}"));
+        if let Some(api_key) = &api_key {
+            for (name, value) in api_key.headers("POST", endpoint, "") {
+                request = request.header(name, value);
+            }
+        }
+        let resp: serde_json::Value = request.send()?.error_for_status()?.json()?;
+
+        let mut credentials = Self::from_bullet(&resp)?;
+        credentials.market = market;
+        credentials.api_key = api_key;
+        credentials.api_domain = api_domain;
+        credentials.proxy = proxy;
+        Ok(credentials)
+    }
+
+    // Reads the instance server and token out of a bullet response
+    fn from_bullet(resp: &serde_json::Value) -> Result<Self, KucoinError> {
+        // KuCoin lists no servers at all during maintenance
+        match &resp["data"]["instanceServers"] {
+            serde_json::Value::Array(servers) if servers.is_empty() => {
+                return Err(KucoinError::NoInstanceServers)
+            }
+            _ => (),
+        }
 
         let wss_domain = match resp["data"]["instanceServers"][0]["endpoint"].to_owned() {
             serde_json::Value::String(s) => s,
-            unexpected => return Err(format!("Unexpected endpoint value: {}", unexpected).into()),
+            unexpected => {
+                return Err(KucoinError::Token(format!(
+                    "Unexpected endpoint value: {}",
+                    unexpected
+                )))
+            }
         };
 
         let token = match resp["data"]["token"].to_owned() {
             serde_json::Value::String(s) => s,
-            unexpected => return Err(format!("Unexpected token value: {}", unexpected).into()),
-        };
-
-        let ping_interval = match resp["data"]["instanceServers"][0]["pingInterval"].to_owned() {
-            serde_json::Value::Number(n) => Duration::from_millis(n.as_u64().unwrap()),
             unexpected => {
-                return Err(format!("Unexpected pingInterval value: {}", unexpected).into())
+                return Err(KucoinError::Token(format!(
+                    "Unexpected token value: {}",
+                    unexpected
+                )))
             }
         };
 
-        let ping_timeout = match resp["data"]["instanceServers"][0]["pingTimeout"].to_owned() {
-            serde_json::Value::Number(n) => Duration::from_millis(n.as_u64().unwrap()),
-            unexpected => {
-                return Err(format!("Unexpected pingTimeout value: {}", unexpected).into())
+        // Milliseconds, which must be a non-negative integer
+        let millis = |key: &str| {
+            let value = &resp["data"]["instanceServers"][0][key];
+            match value.as_u64() {
+                Some(ms) => Ok(Duration::from_millis(ms)),
+                None => Err(KucoinError::Token(format!(
+                    "Unexpected {key} value: {value}"
+                ))),
             }
         };
+        let ping_interval = millis("pingInterval")?;
+        let ping_timeout = millis("pingTimeout")?;
 
         Ok(Self::new(wss_domain, token, ping_interval, ping_timeout))
     }
 
+    /// Fetches the symbols of every futures contract currently open for
+    /// trading, e.g. to subscribe to the whole market through a `SessionPool`.
+    pub fn active_symbols() -> Result<Vec<String>, KucoinError> {
+        let contracts = RestClient::new()?.get_active_contracts()?;
+        Ok(contracts
+            .into_iter()
+            .map(|contract| contract.symbol)
+            .collect())
+    }
+
+    // Domain of futures REST requests, the one tokens are fetched from if
+    // configured, e.g. a sandbox
+    pub(super) fn futures_api_domain(&self) -> &str {
+        self.api_domain.as_deref().unwrap_or(FUTURES_API_DOMAIN)
+    }
+
+    /// Fetches a new token from KuCoin's API, keeping the connection limits
+    /// and ping settings configured on `self`, along with the endpoint set
+    /// by `CredentialsConfig`, if any. Private credentials get a new private
+    /// token.
+    pub fn refresh(&self) -> Result<Self, KucoinError> {
+        let mut credentials = Self::fetch(
+            self.market,
+            self.api_key.clone(),
+            self.api_domain.clone(),
+            self.proxy.clone(),
+        )?;
+        credentials.ping_interval = self.ping_interval;
+        credentials.ping_timeout = self.ping_timeout;
+        credentials.max_message_size = self.max_message_size;
+        credentials.max_frame_size = self.max_frame_size;
+        credentials.extra_params = self.extra_params.clone();
+        if let Some(endpoint) = &self.endpoint_override {
+            credentials.wss_domain = endpoint.clone();
+            credentials.endpoint_override = Some(endpoint.clone());
+        }
+
+        Ok(credentials)
+    }
+
     /// Constructs a Credentials for connecting with KuCoin's WebSocket API
     ///
     /// # Usage
     /// Token can be fetched manually by sending empty POST request to
-    /// https://api.kucoin.com/api/v1/bullet-public
+    /// https://api-futures.kucoin.com/api/v1/bullet-public, or to
+    /// https://api.kucoin.com/api/v1/bullet-public for spot topics after
+    /// setting `market`.
     ///
     /// Consider using `new_with_token` to populate the fields with new token.
     pub fn new(
@@ -67,61 +305,339 @@ impl Credentials {
             token,
             ping_interval,
             ping_timeout,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            extra_params: Vec::new(),
+            market: MarketType::Futures,
+            api_key: None,
+            api_domain: None,
+            proxy: None,
+            endpoint_override: None,
+        }
+    }
+
+    /// Checks that the token is accepted by opening a connection, waiting
+    /// for the server welcome and closing it again.
+    ///
+    /// Tokens can go stale between being fetched and being used; this lets
+    /// callers find out before wiring up subscriptions.
+    pub fn is_valid(&self) -> bool {
+        match client::probe(self) {
+            Ok(welcomed) => welcomed,
+            Err(e) => {
+                log::debug!("Token probe failed: {:?}", e);
+                false
+            }
         }
     }
 
-    // Constructs a connection string for use with WebSockets
+    /// Instance server this connects to, e.g. `wss://ws-api-futures.kucoin.com/`.
+    pub fn endpoint(&self) -> &str {
+        &self.wss_domain
+    }
+
+    // Connection limits for use with WebSockets
+    pub(super) fn websocket_config(&self) -> WebSocketConfig {
+        WebSocketConfig {
+            max_message_size: Some(self.max_message_size),
+            max_frame_size: Some(self.max_frame_size),
+            ..Default::default()
+        }
+    }
+
+    // Constructs a connection string for use with WebSockets. Every call
+    // carries a new connectId, as KuCoin expects one per connection.
     pub fn connection_string(&self) -> String {
-        format!("{}?token={}", self.wss_domain, self.token)
+        let mut connection_string = format!(
+            "{}?token={}&connectId={:016x}",
+            self.wss_domain,
+            self.token,
+            rand::random::<u64>()
+        );
+        for (key, value) in &self.extra_params {
+            connection_string.push_str(&format!("&{key}={value}"));
+        }
+        connection_string
+    }
+}
+
+/// Connection settings loaded from a configuration file, e.g.
+///
+/// ```toml
+/// ping_interval_ms = 18000
+/// max_message_size = 1048576
+/// ```
+///
+/// Unset fields keep the values KuCoin hands out with the token. If both
+/// `endpoint` and `token` are set no token is fetched.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CredentialsConfig {
+    pub endpoint: Option<String>,
+    pub token: Option<String>,
+    pub ping_interval_ms: Option<u64>,
+    pub ping_timeout_ms: Option<u64>,
+    pub max_message_size: Option<usize>,
+    pub max_frame_size: Option<usize>,
+}
+
+impl CredentialsConfig {
+    /// Builds Credentials from the configuration, fetching a token from
+    /// KuCoin's API unless one is supplied along with its endpoint.
+    pub fn into_credentials(self) -> Result<Credentials, KucoinError> {
+        let mut credentials = match (&self.endpoint, self.token) {
+            (Some(endpoint), Some(token)) => Credentials::new(
+                endpoint.clone(),
+                token,
+                DEFAULT_PING_INTERVAL,
+                DEFAULT_PING_TIMEOUT,
+            ),
+            (endpoint, _) => {
+                let mut credentials = Credentials::new_with_token()?;
+                if let Some(endpoint) = endpoint {
+                    credentials.wss_domain = endpoint.clone();
+                }
+                credentials
+            }
+        };
+        // Kept over token refreshes
+        credentials.endpoint_override = self.endpoint;
+
+        if let Some(ms) = self.ping_interval_ms {
+            credentials.ping_interval = Duration::from_millis(ms);
+        }
+        if let Some(ms) = self.ping_timeout_ms {
+            credentials.ping_timeout = Duration::from_millis(ms);
+        }
+        if let Some(size) = self.max_message_size {
+            credentials.max_message_size = size;
+        }
+        if let Some(size) = self.max_frame_size {
+            credentials.max_frame_size = size;
+        }
+
+        Ok(credentials)
     }
 }
 
+// Variants are named after the `type` of the frames, `message` included
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Message {
     Welcome,
     Pong(String),
     Ack(String),
-    Message(serde_json::Value),
+    Error {
+        id: Option<String>,
+        code: i64,
+        reason: String,
+    },
+    /// Data of `topic`, left as text for the route of the topic to parse
+    Message {
+        topic: String,
+        text: String,
+    },
 }
 
 impl Message {
     /// Build a message from a json-formatted String
-    pub fn from_string(msg_str: String) -> Result<Self, serde_json::Error> {
-        let msg: serde_json::Value = serde_json::from_str(msg_str.as_str())?;
-
-        let msg_type = msg
-            .get("type")
-            .expect("Cannot get message type")
-            .as_str()
-            .expect("Message type is not string");
-
-        let id = msg["id"].as_str().ok_or("Cannot get message id");
-
-        let msg = match msg_type {
-            "welcome" => Self::Welcome,
-            "ack" => Self::Ack(id.unwrap().to_owned()),
-            "pong" => Self::Pong(id.unwrap().to_owned()),
-            "message" => Self::Message(msg),
-            other_type => panic!("Message type {other_type} not expected {:?}", msg),
+    pub fn from_string(msg_str: String) -> Result<Self, RecvError> {
+        // Data messages make up nearly all of the traffic, so only their
+        // topic is read here
+        let header: RawHeader = serde_json::from_str(&msg_str)?;
+        if header.kind == "message" {
+            let Some(topic) = header.topic else {
+                return Err(RecvError::KeyNotExists(format!(
+                    "Message has no topic {msg_str}"
+                )));
+            };
+            return Ok(Self::Message {
+                topic: topic.into_owned(),
+                text: msg_str,
+            });
+        }
+
+        let msg = match serde_json::from_str(&msg_str)? {
+            RawMessage::Welcome {} => Self::Welcome,
+            // Acks may carry a failure code rather than come as error frames
+            RawMessage::Ack {
+                id,
+                code,
+                msg,
+                data,
+            } => match code.as_ref().and_then(RawCode::value) {
+                Some(code) if code != 200 && code != 200000 => Self::Error {
+                    id: Some(id),
+                    code,
+                    reason: msg.unwrap_or_else(|| reason(data)),
+                },
+                _ => Self::Ack(id),
+            },
+            RawMessage::Pong { id } => Self::Pong(id),
+            RawMessage::Error { id, code, data } => Self::Error {
+                id,
+                code: code.as_ref().and_then(RawCode::value).unwrap_or_default(),
+                reason: reason(data),
+            },
         };
 
         Ok(msg)
     }
 }
 
-pub fn level2_subscription_string(symbol: &str) -> (String, String) {
-    let topic = format!("/contractMarket/level2Depth5:{symbol}");
-    (
-        json!({
-            "id": 1,
-            "type": "subscribe",
-            "topic": topic.to_owned(),
-            "privateChannel": false,
-            "response": true
-        })
-        .to_string(),
-        topic,
-    )
+// The `type` of a frame and the `topic` of data messages, skipping over the
+// rest of the frame
+#[derive(Deserialize)]
+struct RawHeader<'a> {
+    #[serde(rename = "type", borrow)]
+    kind: Cow<'a, str>,
+    #[serde(default, borrow)]
+    topic: Option<Cow<'a, str>>,
+}
+
+// The frames of KuCoin's WebSocket API other than data messages, told apart
+// by their `type`
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum RawMessage {
+    Welcome {},
+    Ack {
+        id: String,
+        #[serde(default)]
+        code: Option<RawCode>,
+        #[serde(default)]
+        msg: Option<String>,
+        #[serde(default)]
+        data: serde_json::Value,
+    },
+    Pong {
+        id: String,
+    },
+    Error {
+        #[serde(default)]
+        id: Option<String>,
+        #[serde(default)]
+        code: Option<RawCode>,
+        #[serde(default)]
+        data: serde_json::Value,
+    },
+}
+
+// KuCoin sends codes either as numbers or as strings of digits
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawCode {
+    Number(i64),
+    Text(String),
+}
+
+impl RawCode {
+    fn value(&self) -> Option<i64> {
+        match self {
+            RawCode::Number(code) => Some(*code),
+            RawCode::Text(code) => code.parse().ok(),
+        }
+    }
+}
+
+// The reason given in the `data` of a failure, verbatim if it is a string
+fn reason(data: serde_json::Value) -> String {
+    match data {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// Fetches the full level 2 book of a futures `symbol` from the API at
+/// `domain`.
+pub fn level2_snapshot(domain: &str, symbol: &str) -> Result<OrderBook, KucoinError> {
+    let url = format!("{domain}{LEVEL2_SNAPSHOT_ENDPOINT}");
+
+    let client = reqwest::blocking::Client::builder()
+        .use_native_tls()
+        .build()
+        .map_err(KucoinError::TlsUnavailable)?;
+    let resp = client
+        .get(url)
+        .query(&[("symbol", symbol)])
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    Ok(OrderBook::from_snapshot_response(&resp)?)
+}
+
+pub fn subscription_string(id: &str, topic: &Topic, private: bool) -> String {
+    json!({
+        "id": id,
+        "type": "subscribe",
+        "topic": topic.to_string(),
+        "privateChannel": private,
+        "response": true
+    })
+    .to_string()
+}
+
+/// Builds a single subscribe frame for the level 2 topics of all `symbols`
+/// at `depth`, along with the topic each symbol's messages will carry.
+pub fn level2_subscription_string(
+    id: &str,
+    symbols: &[&str],
+    depth: BookDepth,
+) -> (String, Vec<Topic>) {
+    let multiplexed = Topic::level2(&symbols.join(","), depth);
+    let topics = symbols
+        .iter()
+        .map(|symbol| multiplexed.with_symbol(symbol))
+        .collect();
+    (subscription_string(id, &multiplexed, false), topics)
+}
+
+/// Builds the frames subscribing to all `topics` again, along with whether
+/// each is private, multiplexing the symbols of each channel into as few
+/// frames as KuCoin allows. Every frame takes its id from `next_id`.
+pub fn resubscription_strings(
+    topics: impl IntoIterator<Item = (Topic, bool)>,
+    mut next_id: impl FnMut() -> String,
+) -> Vec<String> {
+    let mut channels: HashMap<(Topic, bool), Vec<String>> = HashMap::new();
+    for (topic, private) in topics {
+        match topic.symbol() {
+            Some(symbol) => {
+                let channel = Topic::new(topic.market(), topic.channel(), topic.depth(), None);
+                channels
+                    .entry((channel, private))
+                    .or_default()
+                    .push(symbol.to_owned());
+            }
+            None => {
+                channels.entry((topic, private)).or_default();
+            }
+        }
+    }
+
+    let mut frames = Vec::new();
+    for ((channel, private), symbols) in channels {
+        if symbols.is_empty() {
+            frames.push(subscription_string(&next_id(), &channel, private));
+        }
+        for symbols in symbols.chunks(MAX_SYMBOLS_PER_FRAME) {
+            let topic = channel.with_symbol(&symbols.join(","));
+            frames.push(subscription_string(&next_id(), &topic, private));
+        }
+    }
+    frames
+}
+
+pub fn unsubscription_string(id: &str, topic: &Topic, private: bool) -> String {
+    json!({
+        "id": id,
+        "type": "unsubscribe",
+        "topic": topic.to_string(),
+        "privateChannel": private,
+        "response": true
+    })
+    .to_string()
 }
 
 pub fn ping_string(id: &str) -> String {
@@ -131,3 +647,80 @@ pub fn ping_string(id: &str) -> String {
     })
     .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const HANDED_OUT: &str = "wss://ws-api-futures.kucoin.com/";
+
+    // Answers a single token request with a fresh token, returning the
+    // domain to fetch it from
+    fn serve_token() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request).unwrap();
+
+            let body = json!({
+                "code": "200000",
+                "data": {
+                    "token": "fresh",
+                    "instanceServers": [{
+                        "endpoint": HANDED_OUT,
+                        "pingInterval": 18000,
+                        "pingTimeout": 10000,
+                    }],
+                },
+            })
+            .to_string();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+
+        format!("http://{addr}")
+    }
+
+    fn configured() -> Credentials {
+        let mut credentials = Credentials::new(
+            "wss://stale.example/".to_owned(),
+            "stale".to_owned(),
+            Duration::from_secs(5),
+            Duration::from_secs(2),
+        );
+        credentials.max_message_size = 1 << 10;
+        credentials.api_domain = Some(serve_token());
+        credentials
+    }
+
+    #[test]
+    fn refresh_keeps_configured_settings() {
+        let mut credentials = configured();
+        credentials.endpoint_override = Some("wss://configured.example/".to_owned());
+
+        let refreshed = credentials.refresh().unwrap();
+        assert_eq!(refreshed.token, "fresh");
+        assert_eq!(refreshed.endpoint(), "wss://configured.example/");
+        assert_eq!(refreshed.ping_interval, Duration::from_secs(5));
+        assert_eq!(refreshed.ping_timeout, Duration::from_secs(2));
+        assert_eq!(refreshed.max_message_size, 1 << 10);
+        assert_eq!(refreshed.endpoint_override, credentials.endpoint_override);
+    }
+
+    #[test]
+    fn refresh_connects_to_handed_out_endpoint_unless_configured() {
+        let refreshed = configured().refresh().unwrap();
+        assert_eq!(refreshed.token, "fresh");
+        assert_eq!(refreshed.endpoint(), HANDED_OUT);
+    }
+}
@@ -0,0 +1,411 @@
+use super::api::{self, Credentials, Message};
+use super::book::MarketBook;
+use super::client::lock;
+use super::error::{KucoinError, RecvError};
+use super::topic::Topic;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio::time::{self, Instant};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tungstenite::handshake::client::Response;
+
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Stream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// Resolves a pending request with the server's acknowledgement or rejection.
+type AckSender = oneshot::Sender<Result<(), String>>;
+type Acks = Arc<Mutex<HashMap<String, AckSender>>>;
+
+type Routes = Arc<Mutex<HashMap<Topic, mpsc::UnboundedSender<MarketBook>>>>;
+
+/// Async counterpart of `Session`, running its tasks on the caller's tokio
+/// runtime.
+///
+/// Frames are written by one task and read by another, so pings never wait
+/// on a quiet feed. Dropping the session stops its tasks and closes the
+/// connection.
+///
+/// Unlike `Session`, only level 2 depth 5 books can be subscribed to, and the
+/// session neither reconnects, limits its send rate nor reports errors: once
+/// the connection is lost or a ping goes unanswered, the connection is
+/// dropped and every Receiver sees the end of the feed.
+pub struct AsyncSession {
+    outbound: mpsc::UnboundedSender<String>,
+    routes: Routes,
+    acks: Acks,
+    next_id: Arc<AtomicU64>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl AsyncSession {
+    /// Initiate a WebSocket connection to the server without subscribing to
+    /// any topic, waits for the welcome message and starts pinging.
+    ///
+    /// Must be called within a tokio runtime.
+    pub async fn connect(
+        credentials: &Credentials,
+    ) -> Result<(AsyncSession, Response), KucoinError> {
+        let (mut stream, response) = tokio_tungstenite::connect_async_with_config(
+            credentials.connection_string(),
+            Some(credentials.websocket_config()),
+            false,
+        )
+        .await?;
+
+        let welcome = loop {
+            match stream.next().await {
+                Some(Ok(
+                    msg @ (tungstenite::Message::Text(_) | tungstenite::Message::Binary(_)),
+                )) => break msg.into_text()?,
+                Some(Ok(_)) => (),
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(tungstenite::Error::ConnectionClosed.into()),
+            }
+        };
+        match Message::from_string(welcome)? {
+            Message::Welcome => log::debug!("Client received server welcome"),
+            other => {
+                return Err(RecvError::InvalidFormat(format!(
+                    "Expected a welcome, received {other:?}"
+                ))
+                .into())
+            }
+        }
+
+        let (sink, stream) = stream.split();
+        let (outbound, outbound_recv) = mpsc::unbounded_channel();
+        let (pong_send, pong_recv) = mpsc::unbounded_channel();
+
+        let routes = Routes::default();
+        let acks = Acks::default();
+        let next_id = Arc::new(AtomicU64::new(1));
+
+        // The writer is left out of `tasks`; it closes the connection once
+        // every sender is gone
+        let writer = tokio::spawn(Self::write_loop(sink, outbound_recv));
+        let reader = tokio::spawn(Self::read_loop(
+            stream,
+            outbound.clone(),
+            routes.clone(),
+            acks.clone(),
+            next_id.clone(),
+            pong_send,
+        ));
+        let pinger = tokio::spawn(Self::ping_loop(
+            outbound.clone(),
+            pong_recv,
+            (credentials.ping_interval, credentials.ping_timeout),
+            [writer.abort_handle(), reader.abort_handle()],
+            routes.clone(),
+            acks.clone(),
+        ));
+        let tasks = vec![pinger, reader];
+
+        let session = AsyncSession {
+            outbound,
+            routes,
+            acks,
+            next_id,
+            tasks,
+        };
+
+        Ok((session, response))
+    }
+
+    async fn write_loop(
+        mut sink: SplitSink<Stream, tungstenite::Message>,
+        mut outbound: mpsc::UnboundedReceiver<String>,
+    ) {
+        while let Some(msg) = outbound.recv().await {
+            if let Err(e) = sink.send(tungstenite::Message::Text(msg)).await {
+                log::warn!("Cannot send frame: {e}");
+                return;
+            }
+        }
+
+        if let Err(e) = sink.close().await {
+            log::warn!("Cannot close connection: {e}");
+        }
+    }
+
+    /// Pings every `ping_interval`. Tears the connection down by stopping
+    /// the `connection` tasks if a ping is not answered within
+    /// `ping_timeout`.
+    async fn ping_loop(
+        outbound: mpsc::UnboundedSender<String>,
+        mut pong_recv: mpsc::UnboundedReceiver<String>,
+        (ping_interval, ping_timeout): (Duration, Duration),
+        connection: [AbortHandle; 2],
+        routes: Routes,
+        acks: Acks,
+    ) {
+        for id in 0u64.. {
+            let id = id.to_string();
+            if outbound.send(api::ping_string(&id)).is_err() {
+                return;
+            }
+
+            let sent_at = Instant::now();
+            let answered = time::timeout(ping_timeout, async {
+                while let Some(pong) = pong_recv.recv().await {
+                    if pong == id {
+                        return true;
+                    }
+                }
+                false
+            })
+            .await;
+
+            match answered {
+                Ok(true) => time::sleep_until(sent_at + ping_interval).await,
+                Ok(false) => return,
+                Err(_) => {
+                    log::warn!("No pong within {ping_timeout:?}, dropping the connection");
+                    for task in connection {
+                        task.abort();
+                    }
+                    // The reader is gone, so ends the feed in its place
+                    lock(&routes).clear();
+                    lock(&acks).clear();
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn read_loop(
+        mut stream: SplitStream<Stream>,
+        outbound: mpsc::UnboundedSender<String>,
+        routes: Routes,
+        acks: Acks,
+        next_id: Arc<AtomicU64>,
+        pong_send: mpsc::UnboundedSender<String>,
+    ) {
+        while let Some(frame) = stream.next().await {
+            let text = match frame {
+                Ok(msg @ (tungstenite::Message::Text(_) | tungstenite::Message::Binary(_))) => {
+                    match msg.into_text() {
+                        Ok(text) => text,
+                        Err(e) => {
+                            log::warn!("Cannot read frame: {e}");
+                            continue;
+                        }
+                    }
+                }
+                Ok(_) => continue,
+                Err(e) => {
+                    log::warn!("Connection lost: {e}");
+                    break;
+                }
+            };
+
+            match Message::from_string(text) {
+                Err(e) => log::warn!("{:?}", e),
+                Ok(Message::Pong(id)) => {
+                    let _ = pong_send.send(id);
+                }
+                Ok(Message::Ack(id)) => {
+                    if let Some(ack) = lock(&acks).remove(&id) {
+                        let _ = ack.send(Ok(()));
+                    }
+                }
+                Ok(Message::Error { id, code, reason }) => {
+                    let ack = id.and_then(|id| lock(&acks).remove(&id));
+                    match ack {
+                        Some(ack) => {
+                            let _ = ack.send(Err(format!("{code}: {reason}")));
+                        }
+                        None => log::warn!("Server error {code}: {reason}"),
+                    }
+                }
+                Ok(Message::Message { text, .. }) => {
+                    let received_at = SystemTime::now();
+                    let (mut book, topic) = match MarketBook::from_json(&text) {
+                        Ok(parsed) => parsed,
+                        Err(e) => {
+                            log::warn!("{:?}", e);
+                            continue;
+                        }
+                    };
+                    book.set_received_at(received_at);
+                    let Ok(topic) = topic.parse::<Topic>() else {
+                        continue;
+                    };
+
+                    // Messages may still arrive for a topic that was just unsubscribed
+                    let mut routes = lock(&routes);
+                    if let Some(route) = routes.get(&topic) {
+                        if route.send(book).is_err() {
+                            routes.remove(&topic);
+                            let id = next_id.fetch_add(1, Ordering::Relaxed).to_string();
+                            let _ = outbound.send(api::unsubscription_string(&id, &topic, false));
+                        }
+                    }
+                }
+                Ok(other) => log::warn!("Received unexpected {:?}", other),
+            }
+        }
+
+        // Lets receivers see the end of the feed
+        lock(&routes).clear();
+    }
+
+    fn next_request_id(&self) -> String {
+        self.next_id.fetch_add(1, Ordering::Relaxed).to_string()
+    }
+
+    /// Subscribes to a level 2 depth 5 topic and waits for the server to
+    /// acknowledge it. Fails if the session is already subscribed to it.
+    ///
+    /// ## Returns
+    /// A Receiver of MarketBook. The topic is unsubscribed once it is dropped
+    /// and the next book of the topic arrives, or by `unsubscribe`.
+    pub async fn subscribe_level2(
+        &self,
+        symbol: &str,
+    ) -> Result<mpsc::UnboundedReceiver<MarketBook>, KucoinError> {
+        let id = self.next_request_id();
+        let topic = Topic::level2_depth5(symbol);
+
+        let (send, recv) = mpsc::unbounded_channel();
+        match lock(&self.routes).entry(topic.clone()) {
+            Entry::Occupied(_) => {
+                return Err(KucoinError::AlreadySubscribed {
+                    topic: topic.to_string(),
+                })
+            }
+            Entry::Vacant(entry) => entry.insert(send),
+        };
+        let (ack_send, ack_recv) = oneshot::channel();
+        lock(&self.acks).insert(id.clone(), ack_send);
+
+        let closed = || KucoinError::ChannelClosed {
+            symbol: symbol.to_owned(),
+        };
+        let sent = self
+            .outbound
+            .send(api::subscription_string(&id, &topic, false));
+
+        let result = match sent {
+            Err(_) => Err(closed()),
+            Ok(()) => match time::timeout(ACK_TIMEOUT, ack_recv).await {
+                Ok(Ok(Ok(()))) => return Ok(recv),
+                Ok(Ok(Err(reason))) => Err(KucoinError::SubscriptionRejected {
+                    symbol: symbol.to_owned(),
+                    reason,
+                }),
+                Ok(Err(_)) => Err(closed()),
+                Err(_) => Err(KucoinError::SubscriptionTimeout {
+                    symbol: symbol.to_owned(),
+                }),
+            },
+        };
+
+        lock(&self.acks).remove(&id);
+        lock(&self.routes).remove(&topic);
+        result
+    }
+
+    /// Stops routing books of `topic` and tells the server to stop sending
+    /// them. The Receiver of the topic sees its channel close once the books
+    /// already waiting in it are read.
+    ///
+    /// ## Returns
+    /// Whether the session was subscribed to `topic`. Nothing is sent if it
+    /// was not.
+    pub fn unsubscribe(&self, topic: &Topic) -> Result<bool, KucoinError> {
+        if lock(&self.routes).remove(topic).is_none() {
+            return Ok(false);
+        }
+
+        let id = self.next_request_id();
+        self.outbound
+            .send(api::unsubscription_string(&id, topic, false))
+            .map_err(|_| KucoinError::ChannelClosed {
+                symbol: topic.symbol().unwrap_or_default().to_owned(),
+            })?;
+        Ok(true)
+    }
+}
+
+impl Drop for AsyncSession {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::client::tests::serve;
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    const SYMBOL: &str = "XBTUSDM";
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn runtime() -> Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn drops_connection_once_ping_times_out() {
+        let (mut credentials, _) = serve(Vec::new(), false);
+        credentials.ping_timeout = Duration::from_millis(200);
+
+        runtime().block_on(async {
+            let (session, _) = AsyncSession::connect(&credentials).await.unwrap();
+            let mut books = session.subscribe_level2(SYMBOL).await.unwrap();
+
+            assert!(matches!(
+                time::timeout(TIMEOUT, books.recv()).await,
+                Ok(None)
+            ));
+            // A failed subscription leaves nothing behind, so it fails the
+            // same way when retried
+            for _ in 0..2 {
+                assert!(matches!(
+                    session.subscribe_level2("ETHUSDTM").await,
+                    Err(KucoinError::ChannelClosed { .. })
+                ));
+            }
+            assert!(lock(&session.routes).is_empty());
+            assert!(lock(&session.acks).is_empty());
+        });
+    }
+
+    #[test]
+    fn unsubscribes_and_refuses_duplicates() {
+        let (credentials, _) = serve(Vec::new(), true);
+        let topic = Topic::level2_depth5(SYMBOL);
+
+        runtime().block_on(async {
+            let (session, _) = AsyncSession::connect(&credentials).await.unwrap();
+            let mut books = session.subscribe_level2(SYMBOL).await.unwrap();
+            assert!(matches!(
+                session.subscribe_level2(SYMBOL).await,
+                Err(KucoinError::AlreadySubscribed { .. })
+            ));
+
+            assert!(session.unsubscribe(&topic).unwrap());
+            assert!(matches!(
+                time::timeout(TIMEOUT, books.recv()).await,
+                Ok(None)
+            ));
+            assert!(!session.unsubscribe(&topic).unwrap());
+        });
+    }
+}
@@ -1,63 +1,1084 @@
+use super::contract::ContractInfo;
 use super::error::RecvError;
-use std::fmt::{Display, Formatter, Error};
+use super::json::payload_from_str;
+use super::topic::Topic;
+#[cfg(feature = "decimal")]
+use rust_decimal::Decimal;
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Error, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug)]
+/// Side of an order, as reported by KuCoin (`"buy"`/`"sell"`).
+///
+/// Buy orders rest on the bid side of the book, sell orders on the ask side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn opposite(self) -> Self {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+/// How many levels of each side a level 2 snapshot topic carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BookDepth {
+    #[default]
+    Five,
+    Fifty,
+}
+
+impl BookDepth {
+    /// Number of levels on each side.
+    pub fn levels(self) -> usize {
+        match self {
+            BookDepth::Five => 5,
+            BookDepth::Fifty => 50,
+        }
+    }
+
+    // Depth of the snapshots sent on `topic`, depth 5 unless it says otherwise
+    fn of_topic(topic: &str) -> Self {
+        match topic.parse::<Topic>().ok().and_then(|topic| topic.depth()) {
+            Some(50) => BookDepth::Fifty,
+            _ => BookDepth::Five,
+        }
+    }
+}
+
+/// A price level of the book.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub price: f64,
+    pub size: i64,
+}
+
+/// A level 2 snapshot, holding as many levels on each side as the depth of
+/// the topic it was received on, or the depth it was built with.
+///
+/// Serializes to `{"asks": [[price, size], ...], "bids": [...]}`, padding
+/// included, along with its timestamps, so that snapshots can be stored and
+/// loaded back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "StoredBook")]
 pub struct MarketBook {
-    asks: [(f64, i64); 5],
-    bids: [(f64, i64); 5],
+    asks: Vec<(f64, i64)>,
+    bids: Vec<(f64, i64)>,
+    // When the exchange produced the book, in milliseconds since the epoch
+    timestamp: Option<u64>,
+    // When the session received the book
+    received_at: Option<SystemTime>,
+    // How far the exchange's clock was ahead of the local one, in milliseconds
+    clock_skew: Option<i64>,
+    // Prices of `asks` and `bids` as KuCoin sent them, level by level, if
+    // the book was parsed from a message
+    #[cfg(feature = "decimal")]
+    #[serde(skip)]
+    sent_asks: Vec<Option<Decimal>>,
+    #[cfg(feature = "decimal")]
+    #[serde(skip)]
+    sent_bids: Vec<Option<Decimal>>,
+}
+
+// A stored MarketBook, checked before it is loaded back
+#[derive(Deserialize)]
+struct StoredBook {
+    asks: Vec<(f64, i64)>,
+    bids: Vec<(f64, i64)>,
+    #[serde(default)]
+    timestamp: Option<u64>,
+    #[serde(default)]
+    received_at: Option<SystemTime>,
+    #[serde(default)]
+    clock_skew: Option<i64>,
+}
+
+impl TryFrom<StoredBook> for MarketBook {
+    type Error = String;
+
+    fn try_from(book: StoredBook) -> Result<Self, Self::Error> {
+        if book.asks.len() != book.bids.len() {
+            return Err(format!(
+                "Sides hold {} asks and {} bids instead of as many levels each",
+                book.asks.len(),
+                book.bids.len()
+            ));
+        }
+
+        Ok(MarketBook {
+            asks: book.asks,
+            bids: book.bids,
+            timestamp: book.timestamp,
+            received_at: book.received_at,
+            clock_skew: book.clock_skew,
+            #[cfg(feature = "decimal")]
+            sent_asks: Vec::new(),
+            #[cfg(feature = "decimal")]
+            sent_bids: Vec::new(),
+        })
+    }
 }
 
 impl Display for MarketBook {
     // Tabular format
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "{:<10} {:<10}\t\t{:<10} {:<10}\n", "Ask Price", "Ask Size", "Bid Price", "Bid Size")?;
-        for i in 0..5 {
-            write!(f, "{:<10} {:<10}\t\t{:<10} {:<10}\n", self.asks[i].0, self.asks[i].1, self.bids[i].0, self.bids[i].1)?;
+        writeln!(
+            f,
+            "{:<10} {:<10}\t\t{:<10} {:<10}",
+            "Ask Price", "Ask Size", "Bid Price", "Bid Size"
+        )?;
+        for i in 0..self.depth() {
+            writeln!(
+                f,
+                "{:<10} {:<10}\t\t{:<10} {:<10}",
+                self.asks[i].0, self.asks[i].1, self.bids[i].0, self.bids[i].1
+            )?;
         }
         Ok(())
     }
 }
 
+/// A `MarketBook` expressed in real quantities: prices rounded to the
+/// contract's tick size and sizes converted from contracts to base asset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizedBook {
+    pub asks: Vec<(f64, f64)>,
+    pub bids: Vec<(f64, f64)>,
+}
+
+/// Top of book of a symbol at a point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookSummary {
+    pub best_bid: Option<Level>,
+    pub best_ask: Option<Level>,
+    /// Best ask price minus best bid price, if both sides are present.
+    pub spread: Option<f64>,
+    /// When the book was received.
+    pub updated_at: SystemTime,
+}
+
 impl MarketBook {
-    fn get_asks_bids(data: &serde_json::Value) -> [(f64, i64); 5] {
-        let data = data
-            .as_array()
-            .expect("Data is not an array")
+    // Keeps the `depth` best levels of a side, best price first
+    fn best_levels(mut levels: Vec<(f64, i64)>, side: Side, depth: usize) -> Vec<(f64, i64)> {
+        match side {
+            Side::Buy => levels.sort_by(|a, b| b.0.total_cmp(&a.0)),
+            Side::Sell => levels.sort_by(|a, b| a.0.total_cmp(&b.0)),
+        }
+
+        levels.truncate(depth);
+        levels.resize(depth, (0.0, 0));
+        levels
+    }
+
+    // Same as `best_levels`, along with the prices KuCoin sent for them,
+    // which stop short of the padding
+    #[cfg(feature = "decimal")]
+    fn best_sent_levels(
+        levels: RawLevels,
+        side: Side,
+        depth: usize,
+    ) -> (Vec<(f64, i64)>, Vec<Option<Decimal>>) {
+        let mut sent: Vec<_> = levels.levels.into_iter().zip(levels.prices).collect();
+        match side {
+            Side::Buy => sent.sort_by(|((a, _), _), ((b, _), _)| b.total_cmp(a)),
+            Side::Sell => sent.sort_by(|((a, _), _), ((b, _), _)| a.total_cmp(b)),
+        }
+        sent.truncate(depth);
+
+        let (levels, prices) = sent.into_iter().unzip();
+        (MarketBook::best_levels(levels, side, depth), prices)
+    }
+
+    /// Parses a level 2 message. Levels are kept best price first regardless
+    /// of the order they were sent in, and missing levels are left as
+    /// `(0.0, 0)`.
+    pub fn new(data: serde_json::Value) -> Result<(Self, String), RecvError> {
+        let (book, topic, _) = MarketBook::from_raw(RawBookMessage::deserialize(&data)?);
+        Ok((book, topic))
+    }
+
+    /// Same as `new`, but parses the message text directly instead of going
+    /// through a `serde_json::Value`. Prices and sizes are read straight off
+    /// the text without allocating, which matters on busy depth feeds; see
+    /// the `book_parse` benchmark.
+    pub fn from_json(text: &str) -> Result<(Self, String), RecvError> {
+        let (book, topic, _) = MarketBook::from_json_sequenced(text)?;
+        Ok((book, topic))
+    }
+
+    /// Same as `from_json`, also returning the sequence number of the
+    /// message, if it has one.
+    pub(super) fn from_json_sequenced(
+        text: &str,
+    ) -> Result<(Self, String, Option<u64>), RecvError> {
+        Ok(MarketBook::from_raw(serde_json::from_str(text)?))
+    }
+
+    fn from_raw(msg: RawBookMessage) -> (Self, String, Option<u64>) {
+        let depth = BookDepth::of_topic(&msg.topic);
+        let RawBook {
+            asks,
+            bids,
+            timestamp,
+            sequence,
+        } = msg.data;
+        #[cfg(feature = "decimal")]
+        let ((asks, sent_asks), (bids, sent_bids)) = (
+            MarketBook::best_sent_levels(asks, Side::Sell, depth.levels()),
+            MarketBook::best_sent_levels(bids, Side::Buy, depth.levels()),
+        );
+        #[cfg(not(feature = "decimal"))]
+        let (asks, bids) = (
+            MarketBook::best_levels(asks.levels, Side::Sell, depth.levels()),
+            MarketBook::best_levels(bids.levels, Side::Buy, depth.levels()),
+        );
+        (
+            MarketBook {
+                asks,
+                bids,
+                timestamp,
+                received_at: None,
+                clock_skew: None,
+                #[cfg(feature = "decimal")]
+                sent_asks,
+                #[cfg(feature = "decimal")]
+                sent_bids,
+            },
+            msg.topic.into_owned(),
+            sequence,
+        )
+    }
+
+    /// Builds a book of `depth` levels on each side out of levels of any
+    /// origin, e.g. a book rebuilt from incremental updates. Keeps the best
+    /// `depth` levels of each side and pads the rest.
+    pub fn from_levels(
+        asks: impl IntoIterator<Item = Level>,
+        bids: impl IntoIterator<Item = Level>,
+        depth: usize,
+    ) -> Self {
+        let levels = |levels: Vec<Level>, side| {
+            let levels = levels
+                .into_iter()
+                .map(|level| (level.price, level.size))
+                .collect();
+            MarketBook::best_levels(levels, side, depth)
+        };
+
+        MarketBook {
+            asks: levels(asks.into_iter().collect(), Side::Sell),
+            bids: levels(bids.into_iter().collect(), Side::Buy),
+            timestamp: None,
+            received_at: None,
+            clock_skew: None,
+            #[cfg(feature = "decimal")]
+            sent_asks: Vec::new(),
+            #[cfg(feature = "decimal")]
+            sent_bids: Vec::new(),
+        }
+    }
+
+    /// When the exchange produced the book, in milliseconds since the epoch,
+    /// if the message said.
+    pub fn exchange_timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    /// When the session received the book. `None` for books that were not
+    /// received by a session, e.g. parsed or built by hand.
+    pub fn received_at(&self) -> Option<SystemTime> {
+        self.received_at
+    }
+
+    pub(super) fn set_received_at(&mut self, received_at: SystemTime) {
+        self.received_at = Some(received_at);
+    }
+
+    /// How far the exchange's clock was ahead of the local one when the book
+    /// was received, in milliseconds, if the session measured it. See
+    /// `Session::clock_skew`.
+    pub fn clock_skew(&self) -> Option<i64> {
+        self.clock_skew
+    }
+
+    pub(super) fn set_clock_skew(&mut self, clock_skew: Option<i64>) {
+        self.clock_skew = clock_skew;
+    }
+
+    /// When the exchange produced the book, on the local clock: the exchange
+    /// timestamp corrected by the clock skew, if it is known.
+    pub fn local_timestamp(&self) -> Option<SystemTime> {
+        let produced_at = self.timestamp? as i64 - self.clock_skew.unwrap_or(0);
+        UNIX_EPOCH.checked_add(Duration::from_millis(u64::try_from(produced_at).ok()?))
+    }
+
+    /// Time from the exchange producing the book to the session receiving
+    /// it, corrected by the clock skew if known. `None` unless both times are
+    /// known, or if the local clock is behind.
+    pub fn latency(&self) -> Option<Duration> {
+        self.received_at?
+            .duration_since(self.local_timestamp()?)
+            .ok()
+    }
+
+    /// Number of levels the book holds on each side, present or not.
+    pub fn depth(&self) -> usize {
+        self.asks.len()
+    }
+
+    /// Levels resting on the given side: bids for `Side::Buy`, asks for `Side::Sell`.
+    pub fn levels(&self, side: Side) -> &[(f64, i64)] {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
+    /// Price of the `n`th level of `side` as KuCoin sent it, if the book
+    /// was parsed from a message.
+    #[cfg(feature = "decimal")]
+    pub(super) fn sent_price(&self, side: Side, n: usize) -> Option<Decimal> {
+        let sent = match side {
+            Side::Buy => &self.sent_bids,
+            Side::Sell => &self.sent_asks,
+        };
+        sent.get(n).copied().flatten()
+    }
+
+    /// The `n`th best level (0-indexed) on `side`: bids for `Side::Buy`, asks
+    /// for `Side::Sell`. `None` if the book has no such level.
+    pub fn level(&self, side: Side, n: usize) -> Option<Level> {
+        self.levels(side)
+            .get(n)
+            .filter(|(_, size)| *size > 0)
+            .map(|&(price, size)| Level { price, size })
+    }
+
+    /// Ask levels present in the book, lowest price first.
+    pub fn asks(&self) -> Vec<Level> {
+        self.asks_iter().collect()
+    }
+
+    /// Bid levels present in the book, highest price first.
+    pub fn bids(&self) -> Vec<Level> {
+        self.bids_iter().collect()
+    }
+
+    /// The lowest ask, if the ask side is not empty.
+    pub fn best_ask(&self) -> Option<Level> {
+        self.asks_iter().next()
+    }
+
+    /// The highest bid, if the bid side is not empty.
+    pub fn best_bid(&self) -> Option<Level> {
+        self.bids_iter().next()
+    }
+
+    /// Ask levels present in the book, lowest price first.
+    pub fn asks_iter(&self) -> impl Iterator<Item = Level> + '_ {
+        MarketBook::present_levels(&self.asks)
+    }
+
+    /// Bid levels present in the book, highest price first.
+    pub fn bids_iter(&self) -> impl Iterator<Item = Level> + '_ {
+        MarketBook::present_levels(&self.bids)
+    }
+
+    // Skips the padding of sides with fewer levels than the depth
+    fn present_levels(levels: &[(f64, i64)]) -> impl Iterator<Item = Level> + '_ {
+        levels
             .iter()
-            .map(|x| {
-                let price = x
-                    .get(0)
-                    .expect("Cannot get price from ask")
-                    .as_str()
-                    .expect("Price is not a string");
-                let price = price.parse::<f64>().expect("Price is not a float");
-                let size = x
-                    .get(1)
-                    .expect("Cannot get size from ask")
-                    .as_i64()
-                    .expect("Size is not an integer");
-                (price, size)
+            .filter(|(_, size)| *size > 0)
+            .map(|&(price, size)| Level { price, size })
+    }
+
+    /// Summarizes the book to its top levels.
+    pub fn summary(&self, updated_at: SystemTime) -> BookSummary {
+        let best_bid = self.best_bid();
+        let best_ask = self.best_ask();
+
+        BookSummary {
+            best_bid,
+            best_ask,
+            spread: self.spread(),
+            updated_at,
+        }
+    }
+
+    /// Halfway between the best bid and the best ask. `None` unless both
+    /// sides are present.
+    pub fn mid_price(&self) -> Option<f64> {
+        self.top_prices().map(|(bid, ask)| (bid + ask) / 2.0)
+    }
+
+    /// Best ask price minus best bid price. `None` unless both sides are
+    /// present.
+    pub fn spread(&self) -> Option<f64> {
+        self.top_prices().map(|(bid, ask)| ask - bid)
+    }
+
+    /// The spread in basis points of the mid price. `None` unless both sides
+    /// are present.
+    pub fn spread_bps(&self) -> Option<f64> {
+        Some(self.spread()? / self.mid_price()? * 10_000.0)
+    }
+
+    /// Halfway between the size-weighted average prices of all levels of
+    /// each side, so deeper levels move it too, unlike `mid_price`.
+    ///
+    /// `None` unless both sides are present.
+    pub fn weighted_mid(&self) -> Option<f64> {
+        let average = |levels: &mut dyn Iterator<Item = Level>| {
+            let (notional, size) = levels.fold((0.0, 0), |(notional, size), level| {
+                (
+                    notional + level.price * level.size as f64,
+                    size + level.size,
+                )
             });
+            (size > 0).then(|| notional / size as f64)
+        };
+
+        let bid = average(&mut self.bids_iter())?;
+        let ask = average(&mut self.asks_iter())?;
+        Some((bid + ask) / 2.0)
+    }
 
-        let mut res = [(0.0, 0); 5];
-        for (i, x) in data.enumerate() {
-            res[i] = x;
+    /// Total size resting on `side`: the bids for `Side::Buy`, the asks for
+    /// `Side::Sell`.
+    pub fn total_size(&self, side: Side) -> i64 {
+        self.levels(side).iter().map(|(_, size)| size).sum()
+    }
+
+    /// Mid price weighted by the size on the opposite side of the top of
+    /// book, leaning towards the side more likely to be traded through:
+    /// `(best_ask * bid_size + best_bid * ask_size) / (bid_size + ask_size)`.
+    ///
+    /// `None` unless both sides are present.
+    pub fn microprice(&self) -> Option<f64> {
+        let bid = self.best_bid()?;
+        let ask = self.best_ask()?;
+        let (bid_size, ask_size) = (bid.size as f64, ask.size as f64);
+        Some((ask.price * bid_size + bid.price * ask_size) / (bid_size + ask_size))
+    }
+
+    /// Whether the best bid is above the best ask. Level 2 snapshots can
+    /// briefly show this in fast markets.
+    pub fn is_crossed(&self) -> bool {
+        self.top_prices().is_some_and(|(bid, ask)| bid > ask)
+    }
+
+    /// Whether the best bid and best ask are at the same price.
+    pub fn is_locked(&self) -> bool {
+        self.top_prices().is_some_and(|(bid, ask)| bid == ask)
+    }
+
+    // Best bid and ask prices, if both sides are present
+    fn top_prices(&self) -> Option<(f64, f64)> {
+        let bid = self.best_bid()?;
+        let ask = self.best_ask()?;
+        Some((bid.price, ask.price))
+    }
+
+    /// Converts contract sizes into base asset quantities using the
+    /// contract multiplier, and rounds prices to the tick size, unless it is
+    /// not positive.
+    ///
+    /// On linear contracts one contract stands for `multiplier` of the base
+    /// asset. On inverse contracts, whose multiplier is negative, it stands
+    /// for `|multiplier|` of the quote asset, i.e. `|multiplier| / price` of
+    /// the base asset. Padding levels stay at 0.
+    pub fn normalized(&self, info: &ContractInfo) -> NormalizedBook {
+        let quantity = |price: f64, size: i64| {
+            let quantity = size as f64 * info.multiplier.abs();
+            if info.multiplier >= 0.0 {
+                quantity
+            } else if price > 0.0 {
+                quantity / price
+            } else {
+                0.0
+            }
+        };
+        let normalize = |levels: &[(f64, i64)]| {
+            levels
+                .iter()
+                .map(|&(price, size)| {
+                    let rounded = if info.tick_size > 0.0 {
+                        (price / info.tick_size).round() * info.tick_size
+                    } else {
+                        price
+                    };
+                    (rounded, quantity(price, size))
+                })
+                .collect()
+        };
+
+        NormalizedBook {
+            asks: normalize(&self.asks),
+            bids: normalize(&self.bids),
         }
+    }
 
-        res
+    /// Total size a market order on `side` can fill against the book, i.e.
+    /// the asks for a buy and the bids for a sell.
+    pub fn size_available(&self, side: Side) -> i64 {
+        self.total_size(side.opposite())
     }
-    pub fn new(data: serde_json::Value) -> Result<(Self, String), RecvError> {
-        let topic = data
-            .get("topic").ok_or("key topic not exists".to_string())?
-            .as_str().expect("value of key topic is not a string")
-            .to_string();
-        let data = data.get("data").ok_or("key data not exists".to_string())?;
-
-        let asks = data.get("asks").ok_or("key asks doesn't exists".to_string())?;
-        let bids = data.get("bids").ok_or("key bids doesn't exists".to_string())?;
-        Ok((MarketBook {
-            asks: MarketBook::get_asks_bids(asks),
-            bids: MarketBook::get_asks_bids(bids),
-        }, topic))
+
+    /// Volume-weighted average price and filled quantity of a market order of
+    /// `size` on `side`. The filled quantity is less than `size` when the book
+    /// is too thin.
+    ///
+    /// Returns `None` if nothing can be filled.
+    pub fn fill_price(&self, side: Side, size: i64) -> Option<(f64, i64)> {
+        let mut remaining = size;
+        let mut notional = 0.0;
+
+        for &(price, level_size) in self.levels(side.opposite()) {
+            if remaining <= 0 || level_size <= 0 {
+                break;
+            }
+            let filled = remaining.min(level_size);
+            notional += price * filled as f64;
+            remaining -= filled;
+        }
+
+        let filled = size - remaining;
+        if filled <= 0 {
+            return None;
+        }
+        Some((notional / filled as f64, filled))
+    }
+}
+
+/// A full order book of a symbol, kept up to date from the incremental
+/// level 2 feed (`/contractMarket/level2:{symbol}`), see
+/// `Session::subscribe_order_book`.
+///
+/// Starts from a snapshot and applies every change in sequence order.
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    sequence: u64,
+    asks: BTreeMap<Price, i64>,
+    bids: BTreeMap<Reverse<Price>, i64>,
+}
+
+/// A price ordered by `total_cmp`, so that it can key the levels of a book
+/// and be compared for equality.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Price(pub f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl OrderBook {
+    /// Builds a book from the data of a level 2 snapshot, as returned by
+    /// KuCoin's `/api/v1/level2/snapshot` endpoint.
+    pub fn from_snapshot(data: &serde_json::Value) -> Result<Self, RecvError> {
+        Ok(OrderBook::from_raw(RawSnapshot::deserialize(data)?))
+    }
+
+    /// Same as `from_snapshot`, parsing the text of the whole response.
+    pub(super) fn from_snapshot_response(text: &str) -> Result<Self, RecvError> {
+        Ok(OrderBook::from_raw(payload_from_str(text)?))
+    }
+
+    fn from_raw(snapshot: RawSnapshot) -> Self {
+        let mut book = OrderBook {
+            sequence: snapshot.sequence,
+            ..Default::default()
+        };
+        for (levels, side) in [(snapshot.asks, Side::Sell), (snapshot.bids, Side::Buy)] {
+            for (price, size) in levels.levels {
+                book.set(side, price, size);
+            }
+        }
+        book
+    }
+
+    /// Sequence of the last change applied.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Applies a change of the incremental feed, `"price,side,size"`, setting
+    /// the size of the level at `price`. A size of 0 removes the level.
+    ///
+    /// Changes at or before the current sequence are ignored. The caller is
+    /// expected to check that `sequence` directly follows the current one.
+    pub fn apply(&mut self, sequence: u64, change: &str) -> Result<(), RecvError> {
+        if sequence <= self.sequence {
+            return Ok(());
+        }
+
+        let invalid =
+            || RecvError::InvalidFormat(format!("Change {change} is not price,side,size"));
+        let mut fields = change.split(',');
+        let (Some(price), Some(side), Some(size), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid());
+        };
+        let price = price.parse::<f64>().map_err(|_| invalid())?;
+        let size = size.parse::<i64>().map_err(|_| invalid())?;
+        let side = match side {
+            "buy" => Side::Buy,
+            "sell" => Side::Sell,
+            _ => return Err(invalid()),
+        };
+
+        self.set(side, price, size);
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    // Sets the size of a level, removing it once empty
+    fn set(&mut self, side: Side, price: f64, size: i64) {
+        match side {
+            Side::Buy if size > 0 => {
+                self.bids.insert(Reverse(Price(price)), size);
+            }
+            Side::Buy => {
+                self.bids.remove(&Reverse(Price(price)));
+            }
+            Side::Sell if size > 0 => {
+                self.asks.insert(Price(price), size);
+            }
+            Side::Sell => {
+                self.asks.remove(&Price(price));
+            }
+        }
+    }
+
+    /// Ask levels, lowest price first.
+    pub fn asks_iter(&self) -> impl Iterator<Item = Level> + '_ {
+        self.asks
+            .iter()
+            .map(|(&Price(price), &size)| Level { price, size })
+    }
+
+    /// Bid levels, highest price first.
+    pub fn bids_iter(&self) -> impl Iterator<Item = Level> + '_ {
+        self.bids
+            .iter()
+            .map(|(&Reverse(Price(price)), &size)| Level { price, size })
+    }
+
+    pub fn best_bid(&self) -> Option<Level> {
+        self.bids_iter().next()
+    }
+
+    pub fn best_ask(&self) -> Option<Level> {
+        self.asks_iter().next()
+    }
+
+    /// The `depth` best levels of each side as a `MarketBook`, e.g. to reuse
+    /// its analytics.
+    pub fn to_market_book(&self, depth: usize) -> MarketBook {
+        MarketBook::from_levels(
+            self.asks_iter().take(depth),
+            self.bids_iter().take(depth),
+            depth,
+        )
+    }
+}
+
+// A level 2 message borrowing from the text it is parsed from
+#[derive(Deserialize)]
+struct RawBookMessage<'a> {
+    #[serde(borrow)]
+    topic: Cow<'a, str>,
+    data: RawBook,
+}
+
+#[derive(Deserialize)]
+struct RawBook {
+    asks: RawLevels,
+    bids: RawLevels,
+    #[serde(default)]
+    timestamp: Option<u64>,
+    #[serde(default)]
+    sequence: Option<u64>,
+}
+
+// A level 2 snapshot of KuCoin's REST API
+#[derive(Deserialize)]
+struct RawSnapshot {
+    sequence: u64,
+    asks: RawLevels,
+    bids: RawLevels,
+}
+
+// Levels of one side in the order they were sent, from either an array of
+// `[price, size]` pairs or an object mapping prices to sizes
+struct RawLevels {
+    levels: Vec<(f64, i64)>,
+    #[cfg(feature = "decimal")]
+    prices: Vec<Option<Decimal>>,
+}
+
+impl RawLevels {
+    fn with_capacity(capacity: usize) -> Self {
+        RawLevels {
+            levels: Vec::with_capacity(capacity),
+            #[cfg(feature = "decimal")]
+            prices: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn push(&mut self, price: RawPrice, RawSize(size): RawSize) {
+        self.levels.push((price.value, size));
+        #[cfg(feature = "decimal")]
+        self.prices.push(price.decimal);
+    }
+}
+
+impl<'de> Deserialize<'de> for RawLevels {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct LevelsVisitor;
+
+        impl<'de> Visitor<'de> for LevelsVisitor {
+            type Value = RawLevels;
+
+            fn expecting(&self, f: &mut Formatter) -> Result<(), Error> {
+                f.write_str("an array of [price, size] pairs or an object mapping prices to sizes")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<RawLevels, A::Error> {
+                let mut levels = RawLevels::with_capacity(seq.size_hint().unwrap_or(5));
+                while let Some((price, size)) = seq.next_element()? {
+                    levels.push(price, size);
+                }
+                Ok(levels)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawLevels, A::Error> {
+                let mut levels = RawLevels::with_capacity(map.size_hint().unwrap_or(5));
+                while let Some((price, size)) = map.next_entry()? {
+                    levels.push(price, size);
+                }
+                Ok(levels)
+            }
+        }
+
+        deserializer.deserialize_any(LevelsVisitor)
+    }
+}
+
+// A price sent either as a number or as a string. With the `decimal`
+// feature, also kept as a `Decimal` parsed from the same text, since the f64
+// may not convert back to it. Text `Decimal` cannot parse exactly, e.g. in
+// exponent notation, is converted from the f64 instead.
+struct RawPrice {
+    value: f64,
+    #[cfg(feature = "decimal")]
+    decimal: Option<Decimal>,
+}
+
+impl RawPrice {
+    fn parse<E: serde::de::Error>(text: &str) -> Result<Self, E> {
+        let value = text
+            .parse::<f64>()
+            .map_err(|_| E::custom(format!("Price {text} is not a float")))?;
+        Ok(RawPrice {
+            value,
+            #[cfg(feature = "decimal")]
+            decimal: Decimal::from_str_exact(text)
+                .ok()
+                .or_else(|| Decimal::from_f64_retain(value)),
+        })
+    }
+
+    // The decimal of a number is parsed from the shortest text it prints as,
+    // which is the text it was sent as unless that had extra digits
+    fn number<E: serde::de::Error>(value: f64) -> Result<Self, E> {
+        Ok(RawPrice {
+            value,
+            #[cfg(feature = "decimal")]
+            decimal: Decimal::from_str_exact(&value.to_string())
+                .ok()
+                .or_else(|| Decimal::from_f64_retain(value)),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for RawPrice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PriceVisitor;
+
+        impl Visitor<'_> for PriceVisitor {
+            type Value = RawPrice;
+
+            fn expecting(&self, f: &mut Formatter) -> Result<(), Error> {
+                f.write_str("a float price")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<RawPrice, E> {
+                RawPrice::parse(v)
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<RawPrice, E> {
+                RawPrice::number(v)
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<RawPrice, E> {
+                RawPrice::number(v as f64)
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<RawPrice, E> {
+                RawPrice::number(v as f64)
+            }
+        }
+
+        deserializer.deserialize_any(PriceVisitor)
+    }
+}
+
+// A size sent either as an integer or as a string
+struct RawSize(i64);
+
+impl<'de> Deserialize<'de> for RawSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SizeVisitor;
+
+        impl Visitor<'_> for SizeVisitor {
+            type Value = RawSize;
+
+            fn expecting(&self, f: &mut Formatter) -> Result<(), Error> {
+                f.write_str("an integer size")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<RawSize, E> {
+                v.parse::<i64>()
+                    .map(RawSize)
+                    .map_err(|_| E::custom(format!("Size {v} is not an integer")))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<RawSize, E> {
+                Ok(RawSize(v))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<RawSize, E> {
+                i64::try_from(v)
+                    .map(RawSize)
+                    .map_err(|_| E::custom(format!("Size {v} is not an integer")))
+            }
+        }
+
+        deserializer.deserialize_any(SizeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ASKS: [(f64, i64); 5] = [(100.5, 1), (101.0, 2), (101.5, 3), (102.0, 4), (102.5, 5)];
+    const BIDS: [(f64, i64); 5] = [(100.0, 1), (99.5, 2), (99.0, 3), (98.5, 4), (98.0, 5)];
+
+    // `[price, size]` pairs in the given order, prices as strings like KuCoin
+    fn array(levels: &[(f64, i64)]) -> serde_json::Value {
+        levels
+            .iter()
+            .map(|&(price, size)| json!([price.to_string(), size]))
+            .collect()
+    }
+
+    // Prices mapped to sizes. serde_json orders the keys as strings, so
+    // "99.5" comes after "102.5"
+    fn object(levels: &[(f64, i64)]) -> serde_json::Value {
+        let levels: serde_json::Map<String, serde_json::Value> = levels
+            .iter()
+            .map(|&(price, size)| (price.to_string(), json!(size)))
+            .collect();
+        levels.into()
+    }
+
+    fn parse(levels: &serde_json::Value, side: Side) -> Vec<(f64, i64)> {
+        let levels = RawLevels::deserialize(levels).unwrap().levels;
+        MarketBook::best_levels(levels, side, BookDepth::Five.levels())
+    }
+
+    fn padded(levels: &[(f64, i64)]) -> Vec<(f64, i64)> {
+        let mut levels = levels.to_vec();
+        levels.resize(5, (0.0, 0));
+        levels
+    }
+
+    fn reversed(levels: &[(f64, i64)]) -> Vec<(f64, i64)> {
+        levels.iter().rev().copied().collect()
+    }
+
+    #[test]
+    fn pads_up_to_five_levels() {
+        for n in 0..=5 {
+            for asks in [array(&ASKS[..n]), object(&ASKS[..n])] {
+                assert_eq!(parse(&asks, Side::Sell), padded(&ASKS[..n]), "asks {asks}");
+            }
+            for bids in [array(&BIDS[..n]), object(&BIDS[..n])] {
+                assert_eq!(parse(&bids, Side::Buy), padded(&BIDS[..n]), "bids {bids}");
+            }
+        }
+    }
+
+    #[test]
+    fn sorts_reversed_levels() {
+        for n in 0..=5 {
+            let asks = reversed(&ASKS[..n]);
+            for asks in [array(&asks), object(&asks)] {
+                assert_eq!(parse(&asks, Side::Sell), padded(&ASKS[..n]), "asks {asks}");
+            }
+            let bids = reversed(&BIDS[..n]);
+            for bids in [array(&bids), object(&bids)] {
+                assert_eq!(parse(&bids, Side::Buy), padded(&BIDS[..n]), "bids {bids}");
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_best_five_of_more_levels() {
+        let mut asks = reversed(&ASKS);
+        asks.insert(2, (103.0, 6));
+        asks.push((100.0, 7));
+        let mut best = vec![(100.0, 7)];
+        best.extend_from_slice(&ASKS[..4]);
+
+        assert_eq!(parse(&array(&asks), Side::Sell), best);
+        assert_eq!(parse(&object(&asks), Side::Sell), best);
+    }
+
+    #[test]
+    fn accepts_numeric_prices_and_string_sizes() {
+        let bids = json!([[99.5, "2"], [100, 1]]);
+
+        assert_eq!(parse(&bids, Side::Buy), padded(&BIDS[..2]));
+    }
+
+    #[test]
+    fn rejects_malformed_levels() {
+        for levels in [
+            json!("100.5,1"),
+            json!([["one hundred", 1]]),
+            json!([["100.5", 1.5]]),
+            json!({"100.5": "many"}),
+        ] {
+            let parsed = RawLevels::deserialize(&levels).map(|raw| raw.levels);
+            assert!(parsed.is_err(), "{levels} parsed as {parsed:?}");
+        }
+    }
+
+    #[test]
+    fn from_json_matches_value_parsing() {
+        for (asks, bids) in [
+            (array(&reversed(&ASKS)), array(&BIDS[..3])),
+            (object(&ASKS), object(&reversed(&BIDS))),
+        ] {
+            let msg = json!({
+                "type": "message",
+                "topic": "/contractMarket/level2Depth5:XBTUSDM",
+                "data": {"asks": asks, "bids": bids, "sequence": 7, "timestamp": 1700000000000u64},
+            });
+
+            let (book, topic, sequence) =
+                MarketBook::from_json_sequenced(&msg.to_string()).unwrap();
+            let (expected, expected_topic) = MarketBook::new(msg).unwrap();
+            assert_eq!(book.asks(), expected.asks());
+            assert_eq!(book.bids(), expected.bids());
+            assert_eq!(book.exchange_timestamp(), Some(1700000000000));
+            assert_eq!(topic, expected_topic);
+            assert_eq!(sequence, Some(7));
+        }
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn keeps_decimal_prices_as_sent() {
+        use std::str::FromStr;
+
+        // More significant digits than an f64 holds
+        let msg = json!({
+            "topic": "/contractMarket/level2Depth5:XBTUSDM",
+            "data": {"asks": [["0.12345678901234567891", 1], [0.5, 2]], "bids": []},
+        });
+        let decimal = |text| Decimal::from_str(text).unwrap();
+
+        let (book, _) = MarketBook::from_json(&msg.to_string()).unwrap();
+        let asks = book.decimal_levels(Side::Sell).unwrap();
+        assert_eq!(asks[0].price, decimal("0.12345678901234567891"));
+        assert_eq!(asks[1].price, decimal("0.5"));
+        assert!(book.decimal_levels(Side::Buy).unwrap().is_empty());
+
+        // Same f64, different texts on each side; exponent notation
+        let msg = json!({
+            "topic": "/contractMarket/level2Depth5:XBTUSDM",
+            "data": {
+                "asks": [["0.10000000000000000001", 1], ["2.5e-3", 2]],
+                "bids": [["0.1", 3]],
+            },
+        });
+
+        let (book, _) = MarketBook::from_json(&msg.to_string()).unwrap();
+        let asks = book.decimal_levels(Side::Sell).unwrap();
+        assert_eq!(asks[0].price, Decimal::from_f64_retain(2.5e-3).unwrap());
+        assert_eq!(asks[1].price, decimal("0.10000000000000000001"));
+        let bids = book.decimal_levels(Side::Buy).unwrap();
+        assert_eq!(bids[0].price, decimal("0.1"));
+    }
+
+    fn contract(multiplier: f64, tick_size: f64) -> ContractInfo {
+        ContractInfo {
+            symbol: "XBTUSDM".to_owned(),
+            multiplier,
+            tick_size,
+            lot_size: 1,
+            max_leverage: 100.0,
+        }
+    }
+
+    fn levels(levels: &[(f64, i64)]) -> Vec<Level> {
+        levels
+            .iter()
+            .map(|&(price, size)| Level { price, size })
+            .collect()
+    }
+
+    #[test]
+    fn normalizes_linear_contract() {
+        let book = MarketBook::from_levels(levels(&[(100.26, 3)]), levels(&[(99.74, 5)]), 2);
+
+        let normalized = book.normalized(&contract(0.001, 0.5));
+        assert_eq!(normalized.asks, [(100.5, 0.003), (0.0, 0.0)]);
+        assert_eq!(normalized.bids, [(99.5, 0.005), (0.0, 0.0)]);
+    }
+
+    #[test]
+    fn normalizes_inverse_contract() {
+        let book = MarketBook::from_levels(levels(&[(100.0, 50)]), levels(&[(80.0, 4)]), 2);
+
+        let normalized = book.normalized(&contract(-1.0, 0.0));
+        assert_eq!(normalized.asks, [(100.0, 0.5), (0.0, 0.0)]);
+        assert_eq!(normalized.bids, [(80.0, 0.05), (0.0, 0.0)]);
     }
 }
@@ -0,0 +1,322 @@
+use super::api;
+use super::book::OrderBook;
+use super::error::{KucoinError, RecvError};
+use super::json::payload_from_str;
+use super::sequence::FeedEvent;
+use super::topic::Topic;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+// How many snapshots a resync fetches before giving up on the book
+const RESYNC_ATTEMPTS: u32 = 3;
+// How long to wait before resyncing again after a resync gave up
+const RESYNC_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// A message of the incremental feed, borrowing from its text
+#[derive(Deserialize)]
+struct RawChange<'a> {
+    sequence: u64,
+    #[serde(borrow)]
+    change: Cow<'a, str>,
+}
+
+enum State {
+    // Waiting for a snapshot, holding the changes received meanwhile
+    Buffering(Vec<(u64, String)>),
+    Live(OrderBook),
+}
+
+/// Keeps an `OrderBook` in sync with the incremental level 2 feed of one
+/// symbol, fetching a new snapshot whenever a change goes missing.
+#[derive(Clone)]
+pub(super) struct BookSync {
+    topic: Topic,
+    // Domain snapshots are fetched from
+    api_domain: String,
+    state: Arc<Mutex<State>>,
+    // Sequence of the last change received, which the book may be ahead of
+    // right after a snapshot
+    received: Arc<Mutex<Option<u64>>>,
+    books: mpsc::Sender<OrderBook>,
+    feed_events: Option<mpsc::Sender<FeedEvent>>,
+}
+
+impl BookSync {
+    /// Starts buffering changes of `topic` until `resync` is called.
+    pub fn new(
+        topic: &Topic,
+        api_domain: &str,
+        books: mpsc::Sender<OrderBook>,
+        feed_events: Option<mpsc::Sender<FeedEvent>>,
+    ) -> Self {
+        BookSync {
+            topic: topic.clone(),
+            api_domain: api_domain.to_owned(),
+            state: Arc::new(Mutex::new(State::Buffering(Vec::new()))),
+            received: Arc::new(Mutex::new(None)),
+            books,
+            feed_events,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Applies a message of the feed and sends the updated book, unless
+    /// `paused`. Returns `Ok(false)` once the consumer has gone away.
+    ///
+    /// A repeated sequence is skipped, while one dropping below the last
+    /// received is taken as the exchange restarting the topic's sequence:
+    /// it is reported as a `FeedEvent::SequenceReset` and the book resyncs.
+    pub fn on_message(&self, text: &str, paused: bool) -> Result<bool, RecvError> {
+        let RawChange { sequence, change } = payload_from_str(text)?;
+        let change = &*change;
+
+        let previous = self
+            .received
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .replace(sequence);
+        if previous == Some(sequence) {
+            return Ok(true);
+        }
+
+        let mut state = self.state();
+        let book = match &mut *state {
+            State::Buffering(changes) => {
+                changes.push((sequence, change.to_owned()));
+                return Ok(true);
+            }
+            State::Live(book) => book,
+        };
+
+        if let Some(previous) = previous.filter(|previous| sequence < *previous) {
+            self.report(FeedEvent::SequenceReset {
+                topic: self.topic.to_string(),
+                previous,
+                received: sequence,
+            });
+            *state = State::Buffering(vec![(sequence, change.to_owned())]);
+            drop(state);
+            self.spawn_resync();
+            return Ok(true);
+        }
+        // Changes the snapshot already holds
+        if sequence <= book.sequence() {
+            return Ok(true);
+        }
+        if sequence != book.sequence() + 1 {
+            self.report(FeedEvent::Gap {
+                topic: self.topic.to_string(),
+                expected: book.sequence() + 1,
+                received: sequence,
+            });
+            *state = State::Buffering(vec![(sequence, change.to_owned())]);
+            drop(state);
+            self.spawn_resync();
+            return Ok(true);
+        }
+
+        book.apply(sequence, change)?;
+        if paused {
+            return Ok(true);
+        }
+        Ok(self.books.send(book.clone()).is_ok())
+    }
+
+    fn report(&self, event: FeedEvent) {
+        match &self.feed_events {
+            Some(feed_events) => {
+                let _ = feed_events.send(event);
+            }
+            None => log::warn!("{:?}", event),
+        }
+    }
+
+    // Resyncs without holding up the receiving thread, retrying until the
+    // book is live again or its subscription is gone
+    fn spawn_resync(&self) {
+        let sync = self.clone();
+        let spawned = thread::Builder::new()
+            .name("kucoin-resync".to_owned())
+            .spawn(move || loop {
+                match sync.resync() {
+                    Ok(()) => {
+                        if let State::Live(book) = &*sync.state() {
+                            sync.report(FeedEvent::Resynced {
+                                topic: sync.topic.to_string(),
+                                sequence: book.sequence(),
+                            });
+                        }
+                        return;
+                    }
+                    Err(e) => log::warn!("Cannot resync order book of {}: {e}", sync.topic),
+                }
+
+                thread::sleep(RESYNC_RETRY_DELAY);
+                // Only this thread holds the state once the route is dropped
+                if Arc::strong_count(&sync.state) == 1 {
+                    return;
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("Cannot spawn resync thread: {e}");
+        }
+    }
+
+    /// Fetches a snapshot, applies the changes buffered since and sends the
+    /// resulting book. Changes keep being buffered if none of the snapshots
+    /// fetched lines up with them.
+    pub fn resync(&self) -> Result<(), KucoinError> {
+        let symbol = self.topic.symbol().unwrap_or_default();
+
+        for _ in 0..RESYNC_ATTEMPTS {
+            let mut book = api::level2_snapshot(&self.api_domain, symbol)?;
+
+            let mut state = self.state();
+            let State::Buffering(changes) = &mut *state else {
+                return Ok(());
+            };
+            // A snapshot older than the first buffered change misses some
+            if changes
+                .first()
+                .is_some_and(|(sequence, _)| *sequence > book.sequence() + 1)
+            {
+                continue;
+            }
+
+            for (sequence, change) in changes.iter() {
+                if *sequence > book.sequence() + 1 {
+                    break;
+                }
+                book.apply(*sequence, change)?;
+            }
+            if changes
+                .last()
+                .is_some_and(|(sequence, _)| *sequence > book.sequence())
+            {
+                continue;
+            }
+
+            let _ = self.books.send(book.clone());
+            *state = State::Live(book);
+            return Ok(());
+        }
+
+        Err(KucoinError::Protocol(format!(
+            "no snapshot of {symbol} lines up with its changes"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kucoin::Level;
+    use serde_json::json;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    // Answers a snapshot request with each of `snapshots` in turn, returning
+    // the domain to fetch them from
+    fn serve_snapshots(snapshots: Vec<serde_json::Value>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            for snapshot in snapshots {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).unwrap();
+
+                let body = json!({"code": "200000", "data": snapshot}).to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        format!("http://{addr}")
+    }
+
+    fn change(sequence: u64, change: &str) -> String {
+        json!({"data": {"sequence": sequence, "change": change}}).to_string()
+    }
+
+    #[test]
+    fn resyncs_once_sequence_resets() {
+        let domain = serve_snapshots(vec![
+            json!({"sequence": 100, "asks": [["10", 1]], "bids": [["9", 2]]}),
+            json!({"sequence": 3, "asks": [["11", 4]], "bids": [["8", 5]]}),
+        ]);
+        let (books, received) = mpsc::channel();
+        let (feed_events, events) = mpsc::channel();
+        let sync = BookSync::new(
+            &Topic::level2_changes("XBTUSDM"),
+            &domain,
+            books,
+            Some(feed_events),
+        );
+
+        sync.resync().unwrap();
+        assert_eq!(received.recv_timeout(WAIT).unwrap().sequence(), 100);
+
+        assert!(sync.on_message(&change(101, "10,sell,3"), false).unwrap());
+        let book = received.recv_timeout(WAIT).unwrap();
+        assert_eq!(
+            book.best_ask(),
+            Some(Level {
+                price: 10.0,
+                size: 3
+            })
+        );
+        // Repeated
+        assert!(sync.on_message(&change(101, "10,sell,3"), false).unwrap());
+
+        assert!(sync.on_message(&change(2, "11,sell,6"), false).unwrap());
+        assert_eq!(
+            events.recv_timeout(WAIT).unwrap(),
+            FeedEvent::SequenceReset {
+                topic: "/contractMarket/level2:XBTUSDM".to_owned(),
+                previous: 101,
+                received: 2,
+            }
+        );
+        assert_eq!(
+            events.recv_timeout(WAIT).unwrap(),
+            FeedEvent::Resynced {
+                topic: "/contractMarket/level2:XBTUSDM".to_owned(),
+                sequence: 3,
+            }
+        );
+        let book = received.recv_timeout(WAIT).unwrap();
+        assert_eq!(
+            book.best_ask(),
+            Some(Level {
+                price: 11.0,
+                size: 4
+            })
+        );
+
+        assert!(sync.on_message(&change(4, "11,sell,7"), false).unwrap());
+        let book = received.recv_timeout(WAIT).unwrap();
+        assert_eq!(book.sequence(), 4);
+        assert_eq!(
+            book.best_ask(),
+            Some(Level {
+                price: 11.0,
+                size: 7
+            })
+        );
+        assert!(received.try_recv().is_err());
+    }
+}
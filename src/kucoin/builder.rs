@@ -0,0 +1,288 @@
+use super::api::{Credentials, MarketType, MAX_SYMBOLS_PER_FRAME};
+use super::client::Session;
+use super::clock::{Clock, SystemClock};
+use super::error::{KucoinError, RecvError};
+use super::event::SessionEvent;
+use super::pool::MAX_TOPICS_PER_SESSION;
+use super::rate_limit::checked_send_rate;
+use super::reconnect::Backoff;
+use super::sequence::FeedEvent;
+use super::subscription::{Subscription, SubscriptionOptions};
+use super::token::{ApiTokenProvider, TokenProvider};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use tungstenite::handshake::client::Response;
+
+const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Session settings beyond what is needed to connect.
+#[derive(Clone)]
+pub(super) struct Options {
+    pub stack_size: Option<usize>,
+    pub on_recv_thread_start: Option<Arc<dyn Fn() + Send + Sync>>,
+    pub ping_jitter: f64,
+    pub feed_events: Option<mpsc::Sender<FeedEvent>>,
+    pub clock: Arc<dyn Clock>,
+    pub send_rate: Option<(u32, Duration)>,
+    pub clock_skew_interval: Option<Duration>,
+    pub recv_errors: Option<mpsc::Sender<RecvError>>,
+    pub session_events: Option<mpsc::Sender<SessionEvent>>,
+    pub max_subscriptions: usize,
+    pub ack_timeout: Duration,
+    pub outbound_tap: Option<OutboundTap>,
+    pub reconnect: Option<Backoff>,
+    pub token_provider: Arc<dyn TokenProvider>,
+}
+
+/// Sees the text of every frame a session sends.
+pub(super) type OutboundTap = Arc<dyn Fn(&str) + Send + Sync>;
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            stack_size: None,
+            on_recv_thread_start: None,
+            ping_jitter: 0.0,
+            feed_events: None,
+            clock: Arc::new(SystemClock),
+            send_rate: None,
+            clock_skew_interval: None,
+            recv_errors: None,
+            session_events: None,
+            max_subscriptions: MAX_TOPICS_PER_SESSION,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            outbound_tap: None,
+            reconnect: Some(Backoff::default()),
+            token_provider: Arc::new(ApiTokenProvider),
+        }
+    }
+}
+
+/// Configures and starts a `Session` subscribed to a set of symbols.
+///
+/// # Usage
+/// ```ignore
+/// let (session, response, subscriptions) = SessionBuilder::new(credentials)
+///     .symbol("ETHUSDTM")
+///     .symbol("XBTUSDM")
+///     .strict(true)
+///     .start()?;
+/// ```
+pub struct SessionBuilder {
+    credentials: Credentials,
+    // Market to connect to, if not the one of `credentials`
+    market: Option<MarketType>,
+    symbols: Vec<String>,
+    strict: bool,
+    options: Options,
+}
+
+impl SessionBuilder {
+    pub fn new(credentials: Credentials) -> Self {
+        SessionBuilder {
+            credentials,
+            market: None,
+            symbols: Vec::new(),
+            strict: false,
+            options: Options::default(),
+        }
+    }
+
+    /// Connects to the topics of `market` rather than the market of the
+    /// credentials, fetching a token for it at start, public or private
+    /// like the credentials' own. Reconnects and token rotations stay on
+    /// `market`.
+    ///
+    /// Symbols added with `symbol` are futures topics, so leave them out
+    /// for spot and subscribe with e.g. `Session::subscribe_spot_level2`.
+    pub fn market(mut self, market: MarketType) -> Self {
+        self.market = Some(market);
+        self
+    }
+
+    /// Subscribes to the level 2 depth 5 topic of `symbol` at start.
+    pub fn symbol(mut self, symbol: &str) -> Self {
+        self.symbols.push(symbol.to_string());
+        self
+    }
+
+    /// In strict mode, `start` fails if any subscription is rejected by the
+    /// server or not acknowledged within the ack timeout.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// How long subscribing waits for the server to acknowledge a symbol,
+    /// and strict mode for all subscriptions.
+    pub fn ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.options.ack_timeout = ack_timeout;
+        self
+    }
+
+    /// Stack size of the threads spawned by the session, in bytes.
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.options.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Runs `hook` on the receiving thread before it starts reading, e.g. to
+    /// pin it to a core or raise its priority.
+    pub fn on_recv_thread_start(mut self, hook: impl Fn() + Send + Sync + 'static) -> Self {
+        self.options.on_recv_thread_start = Some(Arc::new(hook));
+        self
+    }
+
+    /// Shortens each wait between pings by a random fraction of the ping
+    /// interval, up to `fraction`, so that many sessions don't ping in sync.
+    ///
+    /// # Panics
+    /// If `fraction` is not within `0.0..=1.0`.
+    pub fn ping_jitter(mut self, fraction: f64) -> Self {
+        self.options.ping_jitter = checked_ping_jitter(fraction);
+        self
+    }
+
+    /// How to reconnect once the connection drops, or `None` to let the
+    /// session stop instead. Reconnects with the default `Backoff` unless
+    /// set.
+    pub fn reconnect(mut self, backoff: Option<Backoff>) -> Self {
+        self.options.reconnect = backoff;
+        self
+    }
+
+    /// Replaces where new credentials come from when the session reconnects
+    /// or rotates its token. Fetches public tokens from KuCoin's API unless
+    /// set.
+    pub fn token_provider(mut self, token_provider: Arc<dyn TokenProvider>) -> Self {
+        self.options.token_provider = token_provider;
+        self
+    }
+
+    /// Runs `tap` with the text of every frame sent to the server, e.g. to
+    /// trace subscribe, unsubscribe and ping frames when debugging.
+    pub fn outbound_tap(mut self, tap: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.options.outbound_tap = Some(Arc::new(tap));
+        self
+    }
+
+    /// Reports sequence gaps and resets of subscribed topics to `sender`,
+    /// along with order book resyncs and crossed books, see `FeedEvent`.
+    pub fn feed_events(mut self, sender: mpsc::Sender<FeedEvent>) -> Self {
+        self.options.feed_events = Some(sender);
+        self
+    }
+
+    /// Hands every error met by the receiving thread to `sender` instead of
+    /// printing it, e.g. to decide when to reconnect. The thread keeps
+    /// receiving after an error either way.
+    pub fn recv_errors(mut self, sender: mpsc::Sender<RecvError>) -> Self {
+        self.options.recv_errors = Some(sender);
+        self
+    }
+
+    /// Hands every error met by the receiving thread to `sender` as a
+    /// `SessionEvent::Error`, and tells it when the session stops receiving
+    /// because the connection was lost for good. Takes the errors over from
+    /// `recv_errors` when both are set.
+    pub fn session_events(mut self, sender: mpsc::Sender<SessionEvent>) -> Self {
+        self.options.session_events = Some(sender);
+        self
+    }
+
+    /// Most topics the session may be subscribed to at once; subscribing
+    /// beyond it fails with `KucoinError::TooManySubscriptions`. Defaults to
+    /// KuCoin's per-connection limit, `MAX_TOPICS_PER_SESSION`.
+    pub fn max_subscriptions(mut self, max: usize) -> Self {
+        self.options.max_subscriptions = max;
+        self
+    }
+
+    /// Replaces the clock the session reads time from, e.g. with a
+    /// `ManualClock` in tests.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.options.clock = clock;
+        self
+    }
+
+    /// Sends at most `messages` subscribe, unsubscribe and raw frames every
+    /// `per`, delaying the ones over the limit. Pings are never delayed.
+    ///
+    /// KuCoin drops connections sending more than 100 messages every 10
+    /// seconds; unlimited by default.
+    ///
+    /// # Panics
+    /// If `messages` or `per` is zero.
+    pub fn send_rate(mut self, messages: u32, per: Duration) -> Self {
+        self.options.send_rate = Some(checked_send_rate(messages, per));
+        self
+    }
+
+    /// Measures how far KuCoin's clock is off the local one every
+    /// `interval`, over the REST API. See `Session::clock_skew`; not
+    /// measured by default.
+    pub fn clock_skew_interval(mut self, interval: Duration) -> Self {
+        self.options.clock_skew_interval = Some(interval);
+        self
+    }
+
+    /// Connects and subscribes to every configured symbol.
+    ///
+    /// ## Returns
+    /// One Subscription per symbol, in the order they were added.
+    pub fn start(self) -> Result<(Session, Response, Vec<Subscription>), KucoinError> {
+        let ack_timeout = self.options.ack_timeout;
+        let mut credentials = self.credentials;
+        if let Some(market) = self.market.filter(|market| *market != credentials.market) {
+            credentials.market = market;
+            credentials = credentials.refresh()?;
+        }
+        let (session, response) = Session::connect_with(&credentials, self.options)?;
+
+        let mut subscriptions = Vec::with_capacity(self.symbols.len());
+        let mut acks = Vec::new();
+        for symbols in self.symbols.chunks(MAX_SYMBOLS_PER_FRAME) {
+            let symbols: Vec<&str> = symbols.iter().map(String::as_str).collect();
+            let (chunk, ack) =
+                session.subscribe_level2_with_ack(&symbols, &SubscriptionOptions::default())?;
+            subscriptions.extend(chunk);
+            acks.push((symbols, ack));
+        }
+
+        if self.strict {
+            let deadline = Instant::now() + ack_timeout;
+            for (symbols, ack) in acks {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match ack.recv_timeout(timeout) {
+                    Ok(Ok(())) => (),
+                    Ok(Err(reason)) => {
+                        // Name the offending symbol if the server told us which one it was
+                        let joined = symbols.join(",");
+                        let named = if reason.contains(&joined) {
+                            None
+                        } else {
+                            symbols.iter().find(|symbol| reason.contains(*symbol))
+                        };
+                        let symbol = named.map_or(joined.clone(), |symbol| symbol.to_string());
+                        return Err(KucoinError::SubscriptionRejected { symbol, reason });
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout | mpsc::RecvTimeoutError::Disconnected) => {
+                        return Err(KucoinError::SubscriptionTimeout {
+                            symbol: symbols.join(","),
+                        })
+                    }
+                }
+            }
+        }
+
+        Ok((session, response, subscriptions))
+    }
+}
+
+pub(super) fn checked_ping_jitter(fraction: f64) -> f64 {
+    assert!(
+        (0.0..=1.0).contains(&fraction),
+        "Ping jitter must be a fraction between 0 and 1"
+    );
+    fraction
+}
@@ -1,85 +1,364 @@
+use super::account::{PositionUpdate, WalletUpdate};
 use super::api;
 use super::api::*;
 use super::book;
+use super::book_sync::BookSync;
+use super::builder::{Options, OutboundTap};
 use super::error;
-use std::collections::HashMap;
+use super::error::KucoinError;
+use super::event::SessionEvent;
+use super::instrument::{Announcement, InstrumentEvent};
+use super::kline::{Candle, KlineInterval};
+use super::level3::Level3Event;
+use super::orders::{OrderUpdate, StopOrderUpdate};
+use super::rate_limit::TokenBucket;
+use super::reconnect::{Backoff, ReconnectEvent, ReconnectHistory};
+use super::rest::RestClient;
+use super::sequence::{FeedEvent, SequenceTracker};
+use super::spot::{SpotBookChanges, SpotTicker, SpotTrade};
+use super::subscription::{CrossedBooks, Subscription, SubscriptionOptions};
+use super::ticker::{MarketSnapshot, Ticker};
+use super::topic::Topic;
+use super::trade::Trade;
+use std::collections::{HashMap, HashSet};
+use std::io;
 use std::net::TcpStream;
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError, RwLock};
 use std::thread;
-use std::time::{Duration, Instant};
-use tungstenite::{handshake::client::Response, stream::MaybeTlsStream};
+use std::time::{Duration, Instant, SystemTime};
+use tungstenite::handshake::{client::Response, HandshakeError};
+use tungstenite::{protocol::WebSocketConfig, stream::MaybeTlsStream};
+
+// How long a read may hold the socket before giving senders a turn.
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// How often the ping thread checks for the pong while waiting for it.
+const PONG_POLL_INTERVAL: Duration = Duration::from_millis(10);
+// How long `get_book_once` waits for the book.
+const BOOK_ONCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Locks `mutex` even if a thread panicked while holding it. Every critical
+// section leaves its data usable, so one panicking consumer or parser must
+// not take the rest of the session down with it.
+pub(super) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
 struct WebSocket {
     net_client: Mutex<tungstenite::WebSocket<MaybeTlsStream<TcpStream>>>,
+    pending_sends: AtomicUsize,
+    outbound_tap: Option<OutboundTap>,
+    shut_down: AtomicBool,
 }
 
 impl WebSocket {
-    fn new(connection_string: String) -> Result<(WebSocket, Response), tungstenite::Error> {
-        let (net_client, response) = tungstenite::connect(connection_string)?;
+    fn new(
+        connection_string: String,
+        config: WebSocketConfig,
+    ) -> Result<(WebSocket, Response), KucoinError> {
+        let (net_client, response) =
+            tungstenite::client::connect_with_config(connection_string, Some(config), 3)?;
 
-        let session = WebSocket {
-            net_client: Mutex::new(net_client),
+        Ok((WebSocket::wrap(net_client)?, response))
+    }
+
+    /// Performs the WebSocket handshake over an already connected `stream`
+    /// instead of opening a connection to the endpoint of `credentials`.
+    fn from_stream(
+        credentials: &Credentials,
+        stream: MaybeTlsStream<TcpStream>,
+    ) -> Result<(WebSocket, Response), KucoinError> {
+        let (net_client, response) = tungstenite::client::client_with_config(
+            credentials.connection_string(),
+            stream,
+            Some(credentials.websocket_config()),
+        )
+        .map_err(|e| match e {
+            HandshakeError::Failure(e) => e,
+            HandshakeError::Interrupted(_) => io::Error::from(io::ErrorKind::WouldBlock).into(),
+        })?;
+
+        Ok((WebSocket::wrap(net_client)?, response))
+    }
+
+    fn wrap(
+        mut net_client: tungstenite::WebSocket<MaybeTlsStream<TcpStream>>,
+    ) -> Result<WebSocket, KucoinError> {
+        let timeout = match net_client.get_mut() {
+            MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(READ_POLL_INTERVAL)),
+            MaybeTlsStream::NativeTls(stream) => {
+                stream.get_mut().set_read_timeout(Some(READ_POLL_INTERVAL))
+            }
+            _ => Ok(()),
         };
+        timeout.map_err(tungstenite::Error::Io)?;
+
+        Ok(WebSocket {
+            net_client: Mutex::new(net_client),
+            pending_sends: AtomicUsize::new(0),
+            outbound_tap: None,
+            shut_down: AtomicBool::new(false),
+        })
+    }
 
-        Ok((session, response))
+    fn connect(credentials: &Credentials) -> Result<(WebSocket, Response), KucoinError> {
+        WebSocket::new(
+            credentials.connection_string(),
+            credentials.websocket_config(),
+        )
     }
 
-    fn send(&self, msg: String) -> Result<(), tungstenite::Error> {
+    /// Connects using `credentials` and waits for the server welcome.
+    fn open(credentials: &Credentials) -> Result<(WebSocket, Response), KucoinError> {
+        let (ws, response) = WebSocket::connect(credentials)?;
+        ws.greet()?;
+
+        Ok((ws, response))
+    }
+
+    /// Waits for the server welcome.
+    fn greet(&self) -> Result<(), KucoinError> {
+        if !self.is_encrypted() {
+            log::warn!("Connection is not encrypted, market data is sent in plaintext");
+        }
+
+        match Message::from_string(self.recv()?)? {
+            Message::Welcome => log::debug!("Client received server welcome"),
+            other => {
+                return Err(error::RecvError::InvalidFormat(format!(
+                    "Expected a welcome, received {other:?}"
+                ))
+                .into())
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_encrypted(&self) -> bool {
+        !matches!(lock(&self.net_client).get_ref(), MaybeTlsStream::Plain(_))
+    }
+
+    fn send(&self, msg: String) -> Result<(), KucoinError> {
+        if let Some(outbound_tap) = &self.outbound_tap {
+            outbound_tap(&msg);
+        }
         let msg = tungstenite::Message::Text(msg);
 
-        let mut net_client = self.net_client.lock().unwrap();
-        net_client.send(msg)
+        self.pending_sends.fetch_add(1, Ordering::SeqCst);
+        let result = lock(&self.net_client).send(msg);
+        self.pending_sends.fetch_sub(1, Ordering::SeqCst);
+
+        Ok(result?)
     }
 
-    fn recv(&self) -> Result<String, tungstenite::Error> {
-        let msg = self.net_client.lock().unwrap().read()?.into_text()?;
-        Ok(msg)
+    fn close(&self) -> Result<(), KucoinError> {
+        self.pending_sends.fetch_add(1, Ordering::SeqCst);
+        let result = lock(&self.net_client).close(None);
+        self.pending_sends.fetch_sub(1, Ordering::SeqCst);
+
+        Ok(result?)
+    }
+
+    /// Closes the connection and makes `recv` give up instead of waiting for
+    /// the server to answer the close frame.
+    fn shut_down(&self) -> Result<(), KucoinError> {
+        self.shut_down.store(true, Ordering::SeqCst);
+        self.close()
     }
+
+    /// Blocks until a data message arrives. The lock is released every
+    /// `READ_POLL_INTERVAL` so that sends are not starved by a quiet feed.
+    ///
+    /// Fragmented messages are reassembled by tungstenite and only returned
+    /// once complete. Control frames are answered by tungstenite and skipped.
+    fn recv(&self) -> Result<String, error::RecvError> {
+        loop {
+            while self.pending_sends.load(Ordering::SeqCst) > 0 {
+                thread::yield_now();
+            }
+
+            match lock(&self.net_client).read() {
+                Ok(msg @ (tungstenite::Message::Text(_) | tungstenite::Message::Binary(_))) => {
+                    return Ok(msg.into_text()?)
+                }
+                // Protocol level keepalives, unrelated to KuCoin's ping messages.
+                // tungstenite always queues the pong for a ping and sends it on
+                // the next read or write; 0.24 has no setting to turn that off.
+                Ok(tungstenite::Message::Ping(_) | tungstenite::Message::Pong(_)) => {
+                    log::trace!("Skipping protocol keepalive frame")
+                }
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if self.shut_down.load(Ordering::SeqCst) {
+                        return Err(tungstenite::Error::ConnectionClosed.into());
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Connects using `credentials`, checks that the server welcomes the client
+/// and disconnects.
+pub(super) fn probe(credentials: &Credentials) -> Result<bool, KucoinError> {
+    let (ws, _) = WebSocket::connect(credentials)?;
+    let welcomed = matches!(Message::from_string(ws.recv()?)?, Message::Welcome);
+    ws.close()?;
+
+    Ok(welcomed)
+}
+
+// Delivers the text of a data message of one topic to its consumer. Returns
+// `Ok(false)` once the consumer has gone away.
+type Route = Box<dyn FnMut(&str) -> Result<bool, error::RecvError> + Send>;
+
+// Parses the text of a data message for the routes reading it as JSON
+fn parse_json(text: &str) -> Result<serde_json::Value, error::RecvError> {
+    Ok(serde_json::from_str(text)?)
 }
 
+// Resolves a pending request with the server's acknowledgement or rejection.
+type AckSender = mpsc::Sender<Result<(), String>>;
+type AckReceiver = mpsc::Receiver<Result<(), String>>;
+
+/// A handle to a session's connection and threads, shared with every
+/// Subscription of it. The last handle to go away closes the session.
 pub struct Session {
-    ws: Arc<WebSocket>,
-    data: Arc<Mutex<HashMap<String, mpsc::Sender<book::MarketBook>>>>,
+    owner: Option<Arc<Owner>>,
+    ws: Arc<RwLock<Arc<WebSocket>>>,
+    credentials: Arc<Mutex<Credentials>>,
+    data: Arc<Mutex<HashMap<Topic, Route>>>,
+    private_topics: Arc<Mutex<HashSet<Topic>>>,
+    acks: Arc<Mutex<HashMap<String, AckSender>>>,
+    next_id: Arc<AtomicU64>,
+    options: Arc<Options>,
+    reconnects: Arc<Mutex<ReconnectHistory>>,
+    latest: Arc<Mutex<HashMap<Topic, (book::MarketBook, SystemTime)>>>,
+    connected_at: Arc<Mutex<Instant>>,
+    reconnect_count: Arc<AtomicU64>,
+    send_limit: Arc<Option<Mutex<TokenBucket>>>,
+    // KuCoin's clock minus the local one in milliseconds, once measured
+    clock_skew: Arc<Mutex<Option<i64>>>,
+    closed: Arc<AtomicBool>,
+    threads: Arc<Mutex<Vec<thread::JoinHandle<()>>>>,
 }
 
 impl Session {
-    /// Initiate a WebSocket connection to the server and returns a handle
-    /// for future operations.
+    /// Initiate a WebSocket connection to the server, subscribes to a level 2
+    /// depth 5 topic and returns a handle for future operations.
+    ///
+    /// See `connect` for the steps performed.
+    pub fn start(
+        credentials: &Credentials,
+        level2_symbol: &str,
+    ) -> Result<(Session, Response, Subscription), KucoinError> {
+        let (session, response) = Self::connect(credentials)?;
+        let rx = session.subscribe_level2(level2_symbol)?;
+
+        Ok((session, response, rx))
+    }
+
+    /// Initiate a WebSocket connection to the server without subscribing to
+    /// any topic and returns a handle for future operations.
     ///
     /// Steps performed:
     /// - Perform TLS handshake
     /// - Poll for welcome message
     /// - Starts a thread that regularly pings the server
-    /// - Subscribes to a level 2 depth 5 topic (can extend to multiple)
     /// - Starts a thread that receives messages from the server
-    pub fn start(
+    pub fn connect(credentials: &Credentials) -> Result<(Session, Response), KucoinError> {
+        Self::connect_with(credentials, Options::default())
+    }
+
+    /// Connects, subscribes to the level 2 depth 5 topic of `symbol` and
+    /// closes the connection again once the first book has arrived.
+    pub fn get_book_once(
         credentials: &Credentials,
-        level2_symbol: &str,
-    ) -> Result<(Session, Response, mpsc::Receiver<book::MarketBook>), tungstenite::Error> {
-        let (ws, response) = WebSocket::new(credentials.connection_string())?;
+        symbol: &str,
+    ) -> Result<book::MarketBook, KucoinError> {
+        let (session, _) = Self::connect(credentials)?;
+        let result = session.subscribe_and_wait(symbol, BOOK_ONCE_TIMEOUT);
+
+        // Dropping the subscription unsubscribes before the connection closes
+        let book = result.map(|(book, _)| book);
+        session.close();
+
+        book
+    }
+
+    pub(super) fn connect_with(
+        credentials: &Credentials,
+        options: Options,
+    ) -> Result<(Session, Response), KucoinError> {
+        let (ws, response) = WebSocket::open(credentials)?;
+
+        Ok((Self::run(ws, credentials, options), response))
+    }
+
+    /// Same as `connect`, but performs the WebSocket handshake over an
+    /// already connected `stream`, e.g. a tunnel or a local test server.
+    /// The endpoint of `credentials` is only used for the request line.
+    ///
+    /// Rotating the token still opens a regular connection.
+    pub fn from_stream(
+        credentials: &Credentials,
+        stream: MaybeTlsStream<TcpStream>,
+    ) -> Result<(Session, Response), KucoinError> {
+        let (ws, response) = WebSocket::from_stream(credentials, stream)?;
+        ws.greet()?;
+
+        Ok((Self::run(ws, credentials, Options::default()), response))
+    }
+
+    // Wraps a welcomed connection and starts the ping and recv threads
+    fn run(mut ws: WebSocket, credentials: &Credentials, options: Options) -> Session {
+        ws.outbound_tap = options.outbound_tap.clone();
         let (pong_send, pong_recv) = mpsc::channel::<String>();
 
-        let session = Self {
-            ws: Arc::new(ws),
+        let connected_at = options.clock.now();
+        let send_limit = options
+            .send_rate
+            .map(|(messages, per)| Mutex::new(TokenBucket::new(messages, per, connected_at)));
+        let mut session = Self {
+            owner: None,
+            ws: Arc::new(RwLock::new(Arc::new(ws))),
+            credentials: Arc::new(Mutex::new(credentials.clone())),
             data: Arc::new(Mutex::new(HashMap::new())),
+            private_topics: Arc::new(Mutex::new(HashSet::new())),
+            acks: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+            options: Arc::new(options),
+            reconnects: Arc::new(Mutex::new(ReconnectHistory::default())),
+            latest: Arc::new(Mutex::new(HashMap::new())),
+            connected_at: Arc::new(Mutex::new(connected_at)),
+            reconnect_count: Arc::new(AtomicU64::new(0)),
+            send_limit: Arc::new(send_limit),
+            clock_skew: Arc::new(Mutex::new(None)),
+            closed: Arc::new(AtomicBool::new(false)),
+            threads: Arc::new(Mutex::new(Vec::new())),
         };
 
-        match session.recv().expect("Cannot receive welcome") {
-            Message::Welcome => println!("Client received server welcome!"),
-            other_type => panic!("Message {:?} not expected", other_type),
-        }
-
         session.spawn_ping_loop(
             pong_recv,
             credentials.ping_timeout,
             credentials.ping_interval,
         );
 
-        let rx = session.subscribe_level2(level2_symbol);
-
         session.spawn_recv_loop(pong_send);
 
-        Ok((session, response, rx))
+        if let Some(interval) = session.options.clock_skew_interval {
+            session.spawn_clock_skew_loop(interval);
+        }
+
+        session.owner = Some(Arc::new(Owner(session.internal())));
+        session
     }
 
     fn spawn_ping_loop(
@@ -94,87 +373,1383 @@ impl Session {
             if a <= b {
                 return Duration::new(0, 0);
             }
-            return a - b;
+            a - b
         }
 
-        let session = self.clone();
+        let session = self.internal();
+        let ping_jitter = self.options.ping_jitter;
+        let clock = self.options.clock.clone();
 
-        thread::spawn(move || loop {
-            let id_str = id.to_string();
-            // Will be blocked by recv loop. Todo: Use async ws,
-            let _ = session.send(ping_string(id_str.as_str()));
+        let handle = self
+            .thread_builder("kucoin-ping")
+            .spawn(move || loop {
+                if session.closed.load(Ordering::SeqCst) {
+                    return;
+                }
+                let id_str = id.to_string();
+                // Taken before sending, so the wait for the pong never
+                // outlasts the ping timeout
+                let send_time = clock.now();
+                let elapsed = || clock.now().saturating_duration_since(send_time);
+                let ws = session.ws();
+                let _ = ws.send(ping_string(id_str.as_str()));
 
-            let send_time = Instant::now();
+                loop {
+                    if session.closed.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    match pong_recv.try_recv() {
+                        Err(mpsc::TryRecvError::Disconnected) => return,
+                        Err(mpsc::TryRecvError::Empty) => (),
+                        Ok(id_recv) => {
+                            if id_recv == id_str {
+                                // Only ever ping early, never later than the server asks
+                                let interval = ping_interval
+                                    .mul_f64(1.0 - rand::random_range(0.0..=ping_jitter));
+                                session.sleep(duration_substract(interval, elapsed()));
+                                break;
+                            }
+                            continue;
+                        }
+                    }
 
-            loop {
-                match pong_recv.recv_timeout(duration_substract(ping_timeout, send_time.elapsed()))
-                {
-                    Err(mpsc::RecvTimeoutError::Disconnected) => return (),
-                    Err(mpsc::RecvTimeoutError::Timeout) => (),
-                    Ok(id_recv) => {
-                        if id_recv == id_str {
-                            thread::sleep(duration_substract(ping_interval, send_time.elapsed()));
-                            break;
+                    let left = duration_substract(ping_timeout, elapsed());
+                    if left.is_zero() {
+                        // The receiving thread then reconnects or stops, as
+                        // for any other lost connection
+                        log::warn!("No pong within {ping_timeout:?}, dropping the connection");
+                        if let Err(e) = ws.shut_down() {
+                            log::debug!("Cannot close unresponsive connection: {e}");
                         }
+                        // Pings the connection replacing it, not the dead one
+                        while Arc::ptr_eq(&ws, &session.ws()) {
+                            if session.closed.load(Ordering::SeqCst) {
+                                return;
+                            }
+                            // Pongs left over from the dead connection are dropped
+                            if let Err(mpsc::TryRecvError::Disconnected) = pong_recv.try_recv() {
+                                return;
+                            }
+                            session.sleep(PONG_POLL_INTERVAL);
+                        }
+                        break;
                     }
+                    session.sleep(left.min(PONG_POLL_INTERVAL));
                 }
-            }
-            id += 1;
-        });
+                id += 1;
+            })
+            .expect("Cannot spawn ping thread");
+        lock(&self.threads).push(handle);
     }
 
     fn spawn_recv_loop(&self, pong_send: mpsc::Sender<String>) {
-        let session = self.clone();
-        thread::spawn(move || loop {
-            match session.recv() {
-                Err(msg) => println!("{:?}", msg),
-                Ok(Message::Pong(id)) => pong_send.send(id).expect("Cannot reach ping thread"),
-                Ok(Message::Ack(_)) => (),
-                Ok(Message::Message(msg)) => {
-                    let (msg, topic) =
-                        book::MarketBook::new(msg).expect(format!("Cannot parse msg").as_str());
-
-                    let data_table = session.data.lock().unwrap();
-                    let chan = data_table
-                        .get(topic.as_str())
-                        .expect(format!("Topic has no channel {}", topic).as_str());
-                    chan.send(msg)
-                        .expect(format!("Cannot send message for topic {:?}", topic).as_str())
-                }
-                Ok(other) => panic!("Received unexpected {:?}", other),
+        let session = self.internal();
+        let on_start = self.options.on_recv_thread_start.clone();
+
+        let handle = self
+            .thread_builder("kucoin-recv")
+            .spawn(move || {
+                if let Some(on_start) = on_start {
+                    on_start();
+                }
+                session.recv_loop(pong_send)
+            })
+            .expect("Cannot spawn recv thread");
+        lock(&self.threads).push(handle);
+    }
+
+    fn spawn_clock_skew_loop(&self, interval: Duration) {
+        let session = self.internal();
+
+        let handle = self
+            .thread_builder("kucoin-clock-skew")
+            .spawn(move || {
+                let rest = match RestClient::new() {
+                    Ok(rest) => rest,
+                    Err(e) => {
+                        log::warn!("Cannot measure clock skew: {e}");
+                        return;
+                    }
+                };
+                while !session.is_closed() {
+                    match rest.measure_clock_skew() {
+                        Ok(clock_skew) => *lock(&session.clock_skew) = Some(clock_skew),
+                        Err(e) => log::warn!("Cannot measure clock skew: {e}"),
+                    }
+                    session.sleep(interval);
+                }
+            })
+            .expect("Cannot spawn clock skew thread");
+        lock(&self.threads).push(handle);
+    }
+
+    fn thread_builder(&self, name: &str) -> thread::Builder {
+        let builder = thread::Builder::new().name(name.to_owned());
+        match self.options.stack_size {
+            Some(stack_size) => builder.stack_size(stack_size),
+            None => builder,
+        }
+    }
+
+    fn recv_loop(&self, pong_send: mpsc::Sender<String>) {
+        // Returning drops `pong_send`, which stops the ping thread too
+        while !self.closed.load(Ordering::SeqCst) {
+            let ws = self.ws();
+            match ws.recv().and_then(Message::from_string) {
+                Err(error::RecvError::NetworkError(_)) if self.closed.load(Ordering::SeqCst) => {
+                    return
+                }
+                // Replaced while being read, e.g. by `rotate_token`
+                Err(error::RecvError::NetworkError(e)) if !Arc::ptr_eq(&ws, &self.ws()) => {
+                    log::debug!("Stopped reading replaced connection: {e}")
+                }
+                Err(error::RecvError::NetworkError(e)) => {
+                    self.report(error::RecvError::NetworkError(e));
+                    let reconnected = match &self.options.reconnect {
+                        Some(backoff) => self.reconnect(backoff),
+                        None => false,
+                    };
+                    if !reconnected {
+                        log::warn!("Connection lost, session stopped");
+                        if let Some(session_events) = &self.options.session_events {
+                            let _ = session_events.send(SessionEvent::Stopped);
+                        }
+                        return;
+                    }
+                }
+                Err(e) => self.report(e),
+                Ok(Message::Pong(id)) => {
+                    // The ping thread is only gone once the session is closing
+                    let _ = pong_send.send(id);
+                }
+                Ok(Message::Ack(id)) => {
+                    if let Some(ack) = lock(&self.acks).remove(&id) {
+                        let _ = ack.send(Ok(()));
+                    }
+                }
+                Ok(Message::Error { id, code, reason }) => {
+                    let ack = id.and_then(|id| lock(&self.acks).remove(&id));
+                    match ack {
+                        Some(ack) => {
+                            let _ = ack.send(Err(format!("{code}: {reason}")));
+                        }
+                        None => log::warn!("Server error {code}: {reason}"),
+                    }
+                }
+                Ok(Message::Message { topic, text }) => {
+                    let topic = match topic.parse::<Topic>() {
+                        Ok(topic) => topic,
+                        Err(e) => {
+                            self.report(e);
+                            continue;
+                        }
+                    };
+
+                    // Messages may still arrive for a topic that was just unsubscribed
+                    let mut data_table = lock(&self.data);
+                    if let Some(route) = data_table.get_mut(&topic) {
+                        match route(&text) {
+                            Ok(true) => (),
+                            Ok(false) => {
+                                data_table.remove(&topic);
+                            }
+                            Err(e) => self.report(e),
+                        }
+                    }
+                }
+                Ok(other) => self.report(error::RecvError::InvalidFormat(format!(
+                    "Received unexpected {:?}",
+                    other
+                ))),
             }
-        });
+        }
+    }
+
+    /// Hands an error of the receiving thread to the consumer that asked for
+    /// them, logging it otherwise.
+    fn report(&self, e: error::RecvError) {
+        match (&self.options.session_events, &self.options.recv_errors) {
+            (Some(session_events), _) => {
+                let _ = session_events.send(SessionEvent::Error(e));
+            }
+            (None, Some(recv_errors)) => {
+                let _ = recv_errors.send(e);
+            }
+            (None, None) => log::error!("{e}"),
+        }
+    }
+
+    /// Shuts the session down: sends a close frame, stops the receiving and
+    /// ping threads and waits for them to finish.
+    ///
+    /// Every Subscription and Receiver of the session sees its channel close
+    /// once the messages already waiting in it are read, and pending
+    /// subscriptions fail. Closing again does nothing.
+    pub fn close(&self) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if let Err(e) = self.ws().shut_down() {
+            log::warn!("Cannot close connection: {e}");
+        }
+
+        // Dropping the routes and acks drops their senders
+        lock(&self.data).clear();
+        lock(&self.acks).clear();
+        lock(&self.latest).clear();
+        lock(&self.private_topics).clear();
+
+        let threads = std::mem::take(&mut *lock(&self.threads));
+        for handle in threads {
+            // A consumer may close the session from the receiving thread
+            if handle.thread().id() != thread::current().id() && handle.join().is_err() {
+                log::warn!("A session thread panicked");
+            }
+        }
+    }
+
+    /// Whether `close` was called.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
     }
 
-    // All send should be done before recv_loop since we don't have async yet
-    fn send(&self, msg: String) -> Result<(), tungstenite::Error> {
-        self.ws.send(msg)
+    /// Sleeps for `duration` on the session's clock, waking up early once
+    /// the session is closed.
+    fn sleep(&self, duration: Duration) {
+        let clock = &self.options.clock;
+        let deadline = clock.now() + duration;
+        while !self.closed.load(Ordering::SeqCst) {
+            let left = deadline.saturating_duration_since(clock.now());
+            if left.is_zero() {
+                return;
+            }
+            clock.sleep(left.min(READ_POLL_INTERVAL));
+        }
+    }
+
+    /// The connection currently in use.
+    fn ws(&self) -> Arc<WebSocket> {
+        self.ws
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn send(&self, msg: String) -> Result<(), KucoinError> {
+        self.ws().send(msg)
+    }
+
+    /// Sends a frame counted against the send rate, waiting for the limit
+    /// to allow it if needed.
+    fn send_limited(&self, msg: String) -> Result<(), KucoinError> {
+        self.throttle();
+        self.send(msg)
     }
 
-    fn recv(&self) -> Result<Message, error::RecvError> {
-        Ok(Message::from_string(self.ws.recv()?)?)
+    fn throttle(&self) {
+        if let Some(send_limit) = self.send_limit.as_ref() {
+            let clock = &self.options.clock;
+            let wait = lock(send_limit).reserve(clock.now());
+            if !wait.is_zero() {
+                log::debug!("Send rate reached, delaying frame by {:?}", wait);
+                self.sleep(wait);
+            }
+        }
     }
 
     fn clone(&self) -> Self {
         Self {
+            owner: self.owner.clone(),
+            ..self.internal()
+        }
+    }
+
+    /// A clone for the session's own threads, which does not keep the
+    /// session open.
+    fn internal(&self) -> Self {
+        Self {
+            owner: None,
             ws: self.ws.clone(),
+            credentials: self.credentials.clone(),
             data: self.data.clone(),
+            private_topics: self.private_topics.clone(),
+            acks: self.acks.clone(),
+            next_id: self.next_id.clone(),
+            options: self.options.clone(),
+            reconnects: self.reconnects.clone(),
+            latest: self.latest.clone(),
+            connected_at: self.connected_at.clone(),
+            reconnect_count: self.reconnect_count.clone(),
+            send_limit: self.send_limit.clone(),
+            clock_skew: self.clock_skew.clone(),
+            closed: self.closed.clone(),
+            threads: self.threads.clone(),
+        }
+    }
+
+    fn next_request_id(&self) -> String {
+        self.next_id.fetch_add(1, Ordering::Relaxed).to_string()
+    }
+
+    /// Instance server the session is currently connected to. May change
+    /// when the token is rotated.
+    pub fn endpoint(&self) -> String {
+        lock(&self.credentials).endpoint().to_owned()
+    }
+
+    /// Whether the underlying connection goes over TLS.
+    pub fn is_encrypted(&self) -> bool {
+        self.ws().is_encrypted()
+    }
+
+    /// Replaces the connection with one using a freshly fetched token,
+    /// without a gap in data.
+    ///
+    /// The new connection is opened and subscribed to every current topic
+    /// before the old one is closed, so books keep flowing to existing
+    /// subscriptions. Some books may be delivered twice around the switch.
+    pub fn rotate_token(&self) -> Result<(), KucoinError> {
+        self.replace_connection(1, Duration::ZERO)
+    }
+
+    /// Switches to a connection using new credentials from the token
+    /// provider and records the attempt.
+    fn replace_connection(&self, attempt: u32, delay: Duration) -> Result<(), KucoinError> {
+        let current = lock(&self.credentials).clone();
+        let (endpoint, result) = match self.options.token_provider.fetch(&current) {
+            Ok(credentials) => (
+                credentials.endpoint().to_owned(),
+                self.switch_connection(credentials),
+            ),
+            Err(e) => (current.endpoint().to_owned(), Err(e)),
+        };
+
+        lock(&self.reconnects).record(ReconnectEvent {
+            endpoint,
+            attempt,
+            delay,
+            time: self.options.clock.system_now(),
+            result: result.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+        });
+
+        result
+    }
+
+    /// Time since the current connection was established.
+    pub fn uptime(&self) -> Duration {
+        let connected_at = *lock(&self.connected_at);
+        self.options
+            .clock
+            .now()
+            .saturating_duration_since(connected_at)
+    }
+
+    /// Number of times the connection has been replaced since the session
+    /// started.
+    pub fn reconnect_count(&self) -> u64 {
+        self.reconnect_count.load(Ordering::Relaxed)
+    }
+
+    /// How far KuCoin's clock is ahead of the local one, in milliseconds,
+    /// negative if it is behind. `None` until measured; see
+    /// `SessionBuilder::clock_skew_interval`.
+    ///
+    /// Books received once it is known carry it, see
+    /// `MarketBook::local_timestamp`.
+    pub fn clock_skew(&self) -> Option<i64> {
+        *lock(&self.clock_skew)
+    }
+
+    /// The most recent reconnect attempts, oldest first.
+    pub fn reconnect_history(&self) -> Vec<ReconnectEvent> {
+        lock(&self.reconnects).events()
+    }
+
+    /// Replaces a dropped connection, retrying as `backoff` allows. Returns
+    /// whether a new connection is in use.
+    fn reconnect(&self, backoff: &Backoff) -> bool {
+        for attempt in 1.. {
+            if backoff.max_attempts.is_some_and(|max| attempt > max) {
+                return false;
+            }
+
+            let delay = backoff.delay(attempt);
+            self.sleep(delay);
+            if self.closed.load(Ordering::SeqCst) {
+                return false;
+            }
+
+            if self.replace_connection(attempt, delay).is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Opens a connection with `credentials`, subscribes it to every current
+    /// topic and only then closes the one in use.
+    fn switch_connection(&self, credentials: Credentials) -> Result<(), KucoinError> {
+        let (mut ws, _) = WebSocket::open(&credentials)?;
+        ws.outbound_tap = self.options.outbound_tap.clone();
+
+        let topics: Vec<(Topic, bool)> = {
+            let private_topics = lock(&self.private_topics);
+            let data = lock(&self.data);
+            data.keys()
+                .map(|topic| (topic.clone(), private_topics.contains(topic)))
+                .collect()
+        };
+        for frame in api::resubscription_strings(topics, || self.next_request_id()) {
+            self.throttle();
+            ws.send(frame)?;
+        }
+
+        let old_ws = std::mem::replace(
+            &mut *self.ws.write().unwrap_or_else(PoisonError::into_inner),
+            Arc::new(ws),
+        );
+        *lock(&self.credentials) = credentials;
+        *lock(&self.connected_at) = self.options.clock.now();
+        self.reconnect_count.fetch_add(1, Ordering::Relaxed);
+
+        // The receiving thread may still be reading the old connection; it
+        // gives up on it and moves over once the read times out
+        if let Err(e) = old_ws.shut_down() {
+            log::warn!("Cannot close rotated connection: {e}");
         }
+
+        Ok(())
+    }
+
+    /// Number of topics this session is routing messages for.
+    pub fn subscription_count(&self) -> usize {
+        lock(&self.data).len()
     }
 
     /// Starts subscribing to a level 2 depth 5 topic
     ///
     /// ## Returns
-    /// A Receiver, receiving MarketBook.
-    fn subscribe_level2(&self, symbol: &str) -> mpsc::Receiver<book::MarketBook> {
-        let (msg, topic) = api::level2_subscription_string(symbol);
+    /// A Subscription, receiving MarketBook. The topic is unsubscribed when
+    /// it is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription. Fails if the
+    /// server rejects it or doesn't answer within the ack timeout, if the
+    /// session already holds as many subscriptions as it may, see
+    /// `SessionBuilder::max_subscriptions`, or if it is already subscribed
+    /// to the topic.
+    pub fn subscribe_level2(&self, symbol: &str) -> Result<Subscription, KucoinError> {
+        self.subscribe_level2_with(symbol, SubscriptionOptions::default())
+    }
+
+    /// Same as `subscribe_level2`, with control over the depth of the books
+    /// and how they are delivered, e.g. to see 50 levels of each side.
+    pub fn subscribe_level2_with(
+        &self,
+        symbol: &str,
+        options: SubscriptionOptions,
+    ) -> Result<Subscription, KucoinError> {
+        let (mut subscriptions, ack) = self.subscribe_level2_with_ack(&[symbol], &options)?;
+        let subscription = subscriptions.remove(0);
+
+        self.wait_for_ack(ack, symbol)?;
+        Ok(subscription)
+    }
+
+    /// Subscribes to the level 2 depth 5 topics of all `symbols` at once,
+    /// sending them comma-separated in as few frames as KuCoin allows.
+    /// Messages are still routed to each symbol by their own topic.
+    ///
+    /// Blocks until the server acknowledges every frame. Fails like
+    /// `subscribe_level2`, naming the symbols of the frame that failed.
+    ///
+    /// ## Returns
+    /// The Subscription of every symbol, by symbol.
+    pub fn subscribe_level2_many(
+        &self,
+        symbols: &[&str],
+    ) -> Result<HashMap<String, Subscription>, KucoinError> {
+        self.subscribe_level2_many_with(symbols, SubscriptionOptions::default())
+    }
+
+    /// Same as `subscribe_level2_many`, with control over the depth of the
+    /// books and how they are delivered, like `subscribe_level2_with`.
+    pub fn subscribe_level2_many_with(
+        &self,
+        symbols: &[&str],
+        options: SubscriptionOptions,
+    ) -> Result<HashMap<String, Subscription>, KucoinError> {
+        let mut subscriptions = HashMap::with_capacity(symbols.len());
+        for chunk in symbols.chunks(MAX_SYMBOLS_PER_FRAME) {
+            let (chunk_subscriptions, ack) = self.subscribe_level2_with_ack(chunk, &options)?;
+            // Unsubscribes the whole frame if it fails
+            let chunk_subscriptions: Vec<_> = chunk
+                .iter()
+                .map(|symbol| symbol.to_string())
+                .zip(chunk_subscriptions)
+                .collect();
+            self.wait_for_ack(ack, &chunk.join(","))?;
+            subscriptions.extend(chunk_subscriptions);
+        }
+
+        Ok(subscriptions)
+    }
+
+    /// Subscribes to the trade execution topic of `symbol`, e.g. to build a
+    /// trade tape alongside its book.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving Trade. The topic is unsubscribed when it is
+    /// dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`.
+    pub fn subscribe_executions(&self, symbol: &str) -> Result<Subscription<Trade>, KucoinError> {
+        self.subscribe_typed(Topic::execution(symbol), symbol, Trade::from_json)
+    }
+
+    /// Subscribes to the best bid and ask of `symbol`, much lighter than its
+    /// level 2 topic for consumers only needing the top of the book.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving Ticker. The topic is unsubscribed when it
+    /// is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`.
+    pub fn subscribe_ticker(&self, symbol: &str) -> Result<Subscription<Ticker>, KucoinError> {
+        self.subscribe_typed(Topic::ticker_v2(symbol), symbol, Ticker::from_json)
+    }
+
+    /// Subscribes to the level 3 topic of `symbol`, following every order
+    /// through the matching engine. Feed the events to a `Level3Book` to
+    /// rebuild the orders resting on the book.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving Level3Event. The topic is unsubscribed when
+    /// it is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`.
+    pub fn subscribe_level3(&self, symbol: &str) -> Result<Subscription<Level3Event>, KucoinError> {
+        self.subscribe_typed(Topic::level3_v2(symbol), symbol, Level3Event::from_json)
+    }
+
+    /// Subscribes to the candles of `symbol` over `interval`. The candle in
+    /// progress is sent again every time a trade updates it.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving Candle. The topic is unsubscribed when it
+    /// is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`.
+    pub fn subscribe_klines(
+        &self,
+        symbol: &str,
+        interval: KlineInterval,
+    ) -> Result<Subscription<Candle>, KucoinError> {
+        self.subscribe_typed(Topic::candles(symbol, interval), symbol, Candle::from_json)
+    }
+
+    /// Subscribes to the mark price, index price and funding rate of
+    /// `symbol`, e.g. for liquidation and funding calculations.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving InstrumentEvent. The topic is unsubscribed
+    /// when it is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`.
+    pub fn subscribe_instrument(
+        &self,
+        symbol: &str,
+    ) -> Result<Subscription<InstrumentEvent>, KucoinError> {
+        self.subscribe_route(Topic::instrument(symbol), symbol, |send, paused| {
+            Box::new(move |text| {
+                let events = InstrumentEvent::parse(text)?;
+                if paused.load(Ordering::Relaxed) {
+                    return Ok(true);
+                }
+                Ok(events.into_iter().all(|event| send.send(event).is_ok()))
+            })
+        })
+    }
+
+    /// Subscribes to the announcements of every futures contract, telling
+    /// when funding settlements begin and end.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving Announcement. The topic is unsubscribed
+    /// when it is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`.
+    pub fn subscribe_announcements(&self) -> Result<Subscription<Announcement>, KucoinError> {
+        let topic = Topic::announcement();
+        self.subscribe_typed(topic.clone(), &topic.to_string(), Announcement::from_json)
+    }
+
+    /// Keeps a full order book of `symbol` from its incremental level 2
+    /// topic, starting from a snapshot fetched from KuCoin's API, or from the
+    /// domain tokens are fetched from if the credentials set one.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving the whole OrderBook after every change.
+    /// The topic is unsubscribed when it is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription and the first
+    /// book is built. A new snapshot is fetched whenever a change goes
+    /// missing, which is also reported as a `FeedEvent::Gap`, or once the
+    /// exchange restarts the topic's sequence, reported as a
+    /// `FeedEvent::SequenceReset`, until one lines up with the changes;
+    /// `FeedEvent::Resynced` follows once it does.
+    pub fn subscribe_order_book(
+        &self,
+        symbol: &str,
+    ) -> Result<Subscription<book::OrderBook>, KucoinError> {
+        let topic = Topic::level2_changes(symbol);
+        let mut sync = None;
+        let subscription = self.subscribe_route(topic.clone(), symbol, |send, paused| {
+            let book_sync = BookSync::new(
+                &topic,
+                lock(&self.credentials).futures_api_domain(),
+                send,
+                self.options.feed_events.clone(),
+            );
+            sync = Some(book_sync.clone());
+            Box::new(move |text| book_sync.on_message(text, paused.load(Ordering::Relaxed)))
+        })?;
+
+        if let Some(sync) = sync {
+            sync.resync()?;
+        }
+        Ok(subscription)
+    }
+
+    /// Subscribes to the rolling 24 hour statistics of `symbol`: volume,
+    /// turnover, last price and price change.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving MarketSnapshot. The topic is unsubscribed
+    /// when it is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`.
+    pub fn subscribe_snapshot(
+        &self,
+        symbol: &str,
+    ) -> Result<Subscription<MarketSnapshot>, KucoinError> {
+        self.subscribe_typed(Topic::snapshot(symbol), symbol, MarketSnapshot::from_json)
+    }
+
+    /// Subscribes to the changes of the book of a spot `symbol`, e.g.
+    /// `BTC-USDT`. The session must have been connected with spot
+    /// credentials, see `Credentials::new_with_token_for`.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving SpotBookChanges. The topic is unsubscribed
+    /// when it is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`.
+    pub fn subscribe_spot_level2(
+        &self,
+        symbol: &str,
+    ) -> Result<Subscription<SpotBookChanges>, KucoinError> {
+        self.subscribe_typed(
+            Topic::spot_level2(symbol),
+            symbol,
+            SpotBookChanges::from_json,
+        )
+    }
+
+    /// Subscribes to the last trade and best bid and ask of a spot
+    /// `symbol`, like `subscribe_spot_level2`.
+    pub fn subscribe_spot_ticker(
+        &self,
+        symbol: &str,
+    ) -> Result<Subscription<SpotTicker>, KucoinError> {
+        self.subscribe_typed(Topic::spot_ticker(symbol), symbol, SpotTicker::from_json)
+    }
+
+    /// Subscribes to the trades of a spot `symbol`, like
+    /// `subscribe_spot_level2`.
+    pub fn subscribe_spot_trades(
+        &self,
+        symbol: &str,
+    ) -> Result<Subscription<SpotTrade>, KucoinError> {
+        self.subscribe_typed(Topic::spot_match(symbol), symbol, SpotTrade::from_json)
+    }
+
+    /// Subscribes to the lifecycle of the account's own futures orders:
+    /// opened, matched, filled, canceled and updated. The session must have
+    /// been connected with `Credentials::new_private`.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving OrderUpdate. The topic is unsubscribed when
+    /// it is dropped.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`.
+    pub fn subscribe_order_updates(&self) -> Result<Subscription<OrderUpdate>, KucoinError> {
+        self.subscribe_private_typed(Topic::trade_orders(), OrderUpdate::from_json)
+    }
+
+    /// Subscribes to the account's own stop orders: placed, triggered and
+    /// canceled. Private, like `subscribe_order_updates`.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving StopOrderUpdate. The topic is unsubscribed
+    /// when it is dropped.
+    pub fn subscribe_stop_orders(&self) -> Result<Subscription<StopOrderUpdate>, KucoinError> {
+        self.subscribe_private_typed(Topic::advanced_orders(), StopOrderUpdate::from_json)
+    }
+
+    /// Subscribes to the account's position in `symbol`: its size, entry
+    /// and liquidation prices, margin and PnL. Private, like
+    /// `subscribe_order_updates`.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving PositionUpdate. The topic is unsubscribed
+    /// when it is dropped.
+    pub fn subscribe_positions(
+        &self,
+        symbol: &str,
+    ) -> Result<Subscription<PositionUpdate>, KucoinError> {
+        self.subscribe_private_typed(Topic::position(symbol), PositionUpdate::from_json)
+    }
+
+    /// Subscribes to the balances of the account's futures wallet, e.g. to
+    /// track risk without polling. Private, like `subscribe_order_updates`.
+    ///
+    /// ## Returns
+    /// A Subscription, receiving WalletUpdate. The topic is unsubscribed
+    /// when it is dropped.
+    pub fn subscribe_wallet(&self) -> Result<Subscription<WalletUpdate>, KucoinError> {
+        self.subscribe_private_typed(Topic::wallet(), WalletUpdate::from_json)
+    }
+
+    /// Subscribes to `topic`, delivering its messages as parsed by `parse`
+    /// from their text, and waits for the server to acknowledge it.
+    fn subscribe_typed<T: Send + 'static>(
+        &self,
+        topic: Topic,
+        symbol: &str,
+        parse: impl Fn(&str) -> Result<T, error::RecvError> + Send + 'static,
+    ) -> Result<Subscription<T>, KucoinError> {
+        self.subscribe_route(topic, symbol, |send, paused| {
+            Box::new(move |text| {
+                let parsed = parse(text)?;
+                if paused.load(Ordering::Relaxed) {
+                    return Ok(true);
+                }
+                Ok(send.send(parsed).is_ok())
+            })
+        })
+    }
+
+    /// Subscribes to `topic` with the route built by `make_route` from the
+    /// sender of the subscription and its pause flag, and waits for the
+    /// server to acknowledge it.
+    fn subscribe_route<T>(
+        &self,
+        topic: Topic,
+        symbol: &str,
+        make_route: impl FnOnce(mpsc::Sender<T>, Arc<AtomicBool>) -> Route,
+    ) -> Result<Subscription<T>, KucoinError> {
+        let max = self.options.max_subscriptions;
+        if self.subscription_count() >= max {
+            return Err(KucoinError::TooManySubscriptions { max });
+        }
+
+        let (send, recv) = mpsc::channel::<T>();
+        let paused = Arc::new(AtomicBool::new(false));
+        self.route(&topic, make_route(send, paused.clone()))?;
+        let subscription = Subscription::new(self.clone(), topic.clone(), recv, paused);
+
+        let id = self.next_request_id();
+        let (ack_send, ack_recv) = mpsc::channel();
+        lock(&self.acks).insert(id.clone(), ack_send);
+        let private = lock(&self.private_topics).contains(&topic);
+
+        // Dropping the subscription on failure unsubscribes again
+        let result = self
+            .send_limited(api::subscription_string(&id, &topic, private))
+            .and_then(|()| self.wait_for_ack(ack_recv, symbol));
+        lock(&self.acks).remove(&id);
+        result.map(|()| subscription)
+    }
+
+    /// Same as `subscribe_typed`, for a private topic.
+    fn subscribe_private_typed<T: Send + 'static>(
+        &self,
+        topic: Topic,
+        parse: impl Fn(&str) -> Result<T, error::RecvError> + Send + 'static,
+    ) -> Result<Subscription<T>, KucoinError> {
+        lock(&self.private_topics).insert(topic.clone());
+        let result = self.subscribe_typed(topic.clone(), &topic.to_string(), parse);
+        if result.is_err() {
+            lock(&self.private_topics).remove(&topic);
+        }
+        result
+    }
+
+    /// Waits for the server to acknowledge a subscription to `symbol`.
+    fn wait_for_ack(&self, ack: AckReceiver, symbol: &str) -> Result<(), KucoinError> {
+        match ack.recv_timeout(self.options.ack_timeout) {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(reason)) => Err(KucoinError::SubscriptionRejected {
+                symbol: symbol.to_owned(),
+                reason,
+            }),
+            Err(mpsc::RecvTimeoutError::Timeout | mpsc::RecvTimeoutError::Disconnected) => {
+                Err(KucoinError::SubscriptionTimeout {
+                    symbol: symbol.to_owned(),
+                })
+            }
+        }
+    }
+
+    /// Latest book received for every subscribed symbol, summarized to top of
+    /// book. Symbols are only listed once their first book has arrived.
+    pub fn snapshot_all(&self) -> HashMap<String, book::BookSummary> {
+        lock(&self.latest)
+            .iter()
+            .map(|(topic, (book, updated_at))| {
+                let symbol = topic.symbol().unwrap_or_default();
+                (symbol.to_owned(), book.summary(*updated_at))
+            })
+            .collect()
+    }
+
+    /// Subscribes to a level 2 depth 5 topic and blocks until the first book
+    /// arrives.
+    ///
+    /// ## Returns
+    /// The first book along with the Subscription receiving the following
+    /// ones, or an error if no book arrives within `timeout`.
+    pub fn subscribe_and_wait(
+        &self,
+        symbol: &str,
+        timeout: Duration,
+    ) -> Result<(book::MarketBook, Subscription), KucoinError> {
+        let subscription = self.subscribe_level2(symbol)?;
+
+        match subscription.recv_timeout(timeout) {
+            Ok(book) => Ok((book, subscription)),
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(KucoinError::BookTimeout {
+                symbol: symbol.to_owned(),
+            }),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(KucoinError::ChannelClosed {
+                symbol: symbol.to_owned(),
+            }),
+        }
+    }
+
+    /// Subscribes to the level 2 topics of all `symbols` at the depth of
+    /// `options` with a single frame, also returning a Receiver that resolves
+    /// once the server acknowledges (`Ok`) or rejects (`Err`) the request.
+    ///
+    /// Nothing is routed or sent if the session is already subscribed to any
+    /// of the topics, or if `symbols` lists one twice.
+    ///
+    /// ## Returns
+    /// One Subscription per symbol, in the same order.
+    pub(super) fn subscribe_level2_with_ack(
+        &self,
+        symbols: &[&str],
+        options: &SubscriptionOptions,
+    ) -> Result<(Vec<Subscription>, AckReceiver), KucoinError> {
+        let max = self.options.max_subscriptions;
+        if self.subscription_count() + symbols.len() > max {
+            return Err(KucoinError::TooManySubscriptions { max });
+        }
+
+        let id = self.next_request_id();
+        let (msg, topics) = api::level2_subscription_string(&id, symbols, options.depth);
+
+        let mut subscriptions = Vec::with_capacity(topics.len());
+        let routes = topics
+            .into_iter()
+            .map(|topic| {
+                let (send, recv) = mpsc::channel::<book::MarketBook>();
+                let paused = Arc::new(AtomicBool::new(false));
+                let route_paused = paused.clone();
+                let latest = self.latest.clone();
+                let latest_topic = topic.clone();
+                let clock_skew = self.clock_skew.clone();
+                let min_interval = options.min_interval;
+                let crossed_books = options.crossed_books;
+                let mut last_delivery: Option<Instant> = None;
+                let mut sequence = SequenceTracker::new(&topic.to_string());
+                let feed_events = self.options.feed_events.clone();
+                let clock = self.options.clock.clone();
+                let route: Route = Box::new(move |text| {
+                    let received_at = clock.system_now();
+                    let (mut book, _, seq) = book::MarketBook::from_json_sequenced(text)?;
+                    let event = seq.and_then(|seq| sequence.observe(seq));
+                    if let (Some(event), Some(feed_events)) = (event, &feed_events) {
+                        let _ = feed_events.send(event);
+                    }
+
+                    book.set_received_at(received_at);
+                    book.set_clock_skew(*lock(&clock_skew));
+                    lock(&latest).insert(latest_topic.clone(), (book.clone(), received_at));
+
+                    if route_paused.load(Ordering::Relaxed) {
+                        return Ok(true);
+                    }
+                    if crossed_books != CrossedBooks::Deliver
+                        && (book.is_crossed() || book.is_locked())
+                    {
+                        if let (Some(feed_events), Some(bid), Some(ask)) =
+                            (&feed_events, book.best_bid(), book.best_ask())
+                        {
+                            let _ = feed_events.send(FeedEvent::CrossedBook {
+                                topic: latest_topic.to_string(),
+                                best_bid: book::Price(bid.price),
+                                best_ask: book::Price(ask.price),
+                            });
+                        }
+                        if crossed_books == CrossedBooks::Drop {
+                            return Ok(true);
+                        }
+                        log::warn!("Book of {latest_topic} is crossed or locked");
+                    }
+                    if let (Some(min_interval), Some(last_delivery)) = (min_interval, last_delivery)
+                    {
+                        if clock.now().saturating_duration_since(last_delivery) < min_interval {
+                            return Ok(true);
+                        }
+                    }
+                    last_delivery = Some(clock.now());
+                    Ok(send.send(book).is_ok())
+                });
+                subscriptions.push((topic.clone(), recv, paused));
+                (topic, route)
+            })
+            .collect();
+        // Subscriptions are only made once routed, as dropping them
+        // unsubscribes
+        self.route_all(routes)?;
+        let subscriptions = subscriptions
+            .into_iter()
+            .map(|(topic, recv, paused)| Subscription::new(self.clone(), topic, recv, paused))
+            .collect();
+
+        let (ack_send, ack_recv) = mpsc::channel();
+        lock(&self.acks).insert(id.clone(), ack_send);
+
+        if let Err(e) = self.send_limited(msg) {
+            lock(&self.acks).remove(&id);
+            return Err(e);
+        }
+
+        Ok((subscriptions, ack_recv))
+    }
+
+    /// Sends an arbitrary JSON frame to the server, e.g. to subscribe to a
+    /// topic this crate doesn't model yet. Pair with `register_topic` to
+    /// receive its messages.
+    pub fn send_raw(&self, json: serde_json::Value) -> Result<(), KucoinError> {
+        self.send_limited(json.to_string())
+    }
+
+    /// Forwards every message of `topic` as raw JSON to the returned Receiver.
+    /// Nothing is sent to the server; see `send_raw`.
+    ///
+    /// Messages stop being routed once the Receiver is dropped. Fails if the
+    /// messages of `topic` are already routed elsewhere, e.g. to a
+    /// Subscription.
+    pub fn register_topic(
+        &self,
+        topic: &Topic,
+    ) -> Result<mpsc::Receiver<serde_json::Value>, KucoinError> {
+        let (send, recv) = mpsc::channel::<serde_json::Value>();
+        self.route(
+            topic,
+            Box::new(move |text| Ok(send.send(parse_json(text)?).is_ok())),
+        )?;
+
+        Ok(recv)
+    }
+
+    /// Subscribes to a private topic, e.g. `/contractMarket/tradeOrders`,
+    /// forwarding every message of it as raw JSON to the returned Receiver.
+    /// The session must have been connected with `Credentials::new_private`.
+    ///
+    /// Blocks until the server acknowledges the subscription, like
+    /// `subscribe_level2`. Messages stop being routed once the Receiver is
+    /// dropped.
+    pub fn subscribe_private(
+        &self,
+        topic: &Topic,
+    ) -> Result<mpsc::Receiver<serde_json::Value>, KucoinError> {
+        let max = self.options.max_subscriptions;
+        if self.subscription_count() >= max {
+            return Err(KucoinError::TooManySubscriptions { max });
+        }
+
+        let recv = self.register_topic(topic)?;
+        lock(&self.private_topics).insert(topic.clone());
+
+        let id = self.next_request_id();
+        let (ack_send, ack_recv) = mpsc::channel();
+        lock(&self.acks).insert(id.clone(), ack_send);
+        let result = self
+            .send_limited(api::subscription_string(&id, topic, true))
+            .and_then(|()| self.wait_for_ack(ack_recv, &topic.to_string()));
+        lock(&self.acks).remove(&id);
+        if result.is_err() {
+            lock(&self.data).remove(topic);
+            lock(&self.private_topics).remove(topic);
+        }
+        result.map(|()| recv)
+    }
+
+    /// Routes the messages of `topic` to `route`. Fails rather than take
+    /// them away from a consumer they are already routed to.
+    fn route(&self, topic: &Topic, route: Route) -> Result<(), KucoinError> {
+        self.route_all(vec![(topic.clone(), route)])
+    }
+
+    /// Same as `route` for many topics at once, routing none of them if any
+    /// is already routed or listed twice.
+    fn route_all(&self, routes: Vec<(Topic, Route)>) -> Result<(), KucoinError> {
+        let mut data = lock(&self.data);
+        for (n, (topic, _)) in routes.iter().enumerate() {
+            if data.contains_key(topic) || routes[..n].iter().any(|(other, _)| other == topic) {
+                return Err(KucoinError::AlreadySubscribed {
+                    topic: topic.to_string(),
+                });
+            }
+        }
+        data.extend(routes);
+        Ok(())
+    }
+
+    /// Stops routing messages of `topic` and tells the server to stop
+    /// sending them. The Subscription or Receiver of the topic sees its
+    /// channel close once the messages already waiting in it are read.
+    ///
+    /// Lets long-running processes change what they watch without keeping
+    /// every Subscription around to drop it.
+    ///
+    /// ## Returns
+    /// Whether the session was subscribed to `topic`. Nothing is sent if it
+    /// was not.
+    pub fn unsubscribe(&self, topic: &Topic) -> Result<bool, KucoinError> {
+        let Some(route) = lock(&self.data).remove(topic) else {
+            return Ok(false);
+        };
+        // Dropping the route drops the sender, closing the consumer's channel
+        drop(route);
+        lock(&self.latest).remove(topic);
+        let private = lock(&self.private_topics).remove(topic);
+
+        let id = self.next_request_id();
+        self.send_limited(api::unsubscription_string(&id, topic, private))?;
+        Ok(true)
+    }
+
+    /// `unsubscribe` for subscriptions going away, which cannot report
+    /// errors.
+    pub(super) fn unsubscribe_topic(&self, topic: &Topic) {
+        if let Err(e) = self.unsubscribe(topic) {
+            log::warn!("Cannot unsubscribe from {topic}: {e}");
+        }
+    }
+}
+
+// Closes the session once the last handle to it is dropped.
+struct Owner(Session);
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::super::clock::ManualClock;
+    use super::super::token::TokenProvider;
+    use super::*;
+    use std::net::{SocketAddr, TcpListener};
+    use tungstenite::protocol::frame::coding::{Data, OpCode};
+    use tungstenite::protocol::frame::Frame;
+
+    const SYMBOL: &str = "XBTUSDM";
+    // How long to wait for something that should happen
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    // How long to wait before concluding that something did not happen
+    const QUIET: Duration = Duration::from_millis(300);
+
+    // Accepts a single connection on a local port and welcomes it. Answers
+    // pings if `pongs` is set, and acknowledges the first subscription
+    // before sending `frames`, until the client goes away.
+    pub(in super::super) fn serve(
+        frames: Vec<tungstenite::Message>,
+        pongs: bool,
+    ) -> (Credentials, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut ws = tungstenite::accept(stream).unwrap();
+            let welcome = r#"{"id":"welcome","type":"welcome"}"#;
+            ws.send(tungstenite::Message::Text(welcome.to_owned()))
+                .unwrap();
+
+            let mut frames = Some(frames);
+            while let Ok(msg) = ws.read() {
+                let Ok(msg) = msg.into_text() else { continue };
+                let Ok(msg) = serde_json::from_str::<serde_json::Value>(&msg) else {
+                    continue;
+                };
+                let reply = match msg["type"].as_str() {
+                    Some("ping") if pongs => "pong",
+                    Some("subscribe") => "ack",
+                    _ => continue,
+                };
+                let reply = serde_json::json!({"id": msg["id"], "type": reply});
+                let _ = ws.send(tungstenite::Message::Text(reply.to_string()));
+                if reply["type"] == "ack" {
+                    for frame in frames.take().into_iter().flatten() {
+                        let _ = ws.send(frame);
+                    }
+                }
+            }
+        });
+
+        let credentials = Credentials::new(
+            format!("ws://{addr}/"),
+            "token".to_owned(),
+            Duration::from_secs(18),
+            Duration::from_secs(10),
+        );
+        (credentials, addr)
+    }
+
+    // Options reading time from `clock`, handing the text of every frame
+    // sent to `sent` and never reconnecting
+    fn manual_options(clock: &Arc<ManualClock>, sent: mpsc::Sender<String>) -> Options {
+        Options {
+            clock: clock.clone(),
+            reconnect: None,
+            outbound_tap: Some(Arc::new(move |frame| {
+                let _ = sent.send(frame.to_owned());
+            })),
+            ..Options::default()
+        }
+    }
+
+    // The next frame of `kind` among those sent, if one is sent in `timeout`
+    fn next_sent(sent: &mpsc::Receiver<String>, kind: &str, timeout: Duration) -> Option<String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let frame = sent.recv_timeout(left).ok()?;
+            let msg: serde_json::Value = serde_json::from_str(&frame).unwrap();
+            if msg["type"] == kind {
+                return Some(frame);
+            }
+        }
+    }
+
+    fn level2_message(sequence: u64) -> String {
+        serde_json::json!({
+            "type": "message",
+            "topic": Topic::level2_depth5(SYMBOL).to_string(),
+            "subject": "level2",
+            "data": {
+                "sequence": sequence,
+                "asks": [["100.5", 1]],
+                "bids": [["100", 2]],
+                "timestamp": 1700000000000u64,
+            },
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn delivers_split_text_frame_once() {
+        let msg = level2_message(1).into_bytes();
+        let (head, tail) = msg.split_at(msg.len() / 2);
+        let frames = vec![
+            tungstenite::Message::Frame(Frame::message(
+                head.to_vec(),
+                OpCode::Data(Data::Text),
+                false,
+            )),
+            // Control frames may come between the fragments of a message
+            tungstenite::Message::Ping(b"keepalive".to_vec()),
+            tungstenite::Message::Frame(Frame::message(
+                tail.to_vec(),
+                OpCode::Data(Data::Continue),
+                true,
+            )),
+        ];
+        let (credentials, addr) = serve(frames, true);
+        let stream = MaybeTlsStream::Plain(TcpStream::connect(addr).unwrap());
+
+        let (session, _) = Session::from_stream(&credentials, stream).unwrap();
+        let books = session.subscribe_level2(SYMBOL).unwrap();
+
+        let book = books.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(book.asks()[0].price, 100.5);
+        assert_eq!(book.bids()[0].size, 2);
+        assert!(matches!(
+            books.recv_timeout(QUIET),
+            Err(mpsc::RecvTimeoutError::Timeout)
+        ));
+
+        session.close();
+    }
+
+    #[test]
+    fn drops_connection_once_ping_times_out() {
+        let (credentials, _) = serve(Vec::new(), false);
+        let clock = Arc::new(ManualClock::new());
+        let (sent_send, sent) = mpsc::channel();
+        let (events_send, events) = mpsc::channel();
+        let options = Options {
+            session_events: Some(events_send),
+            ..manual_options(&clock, sent_send)
+        };
+
+        let (session, _) = Session::connect_with(&credentials, options).unwrap();
+        assert!(next_sent(&sent, "ping", TIMEOUT).is_some());
+
+        clock.advance(credentials.ping_timeout - Duration::from_millis(1));
+        assert!(events.recv_timeout(QUIET).is_err());
+        assert!(!session.is_closed());
+
+        clock.advance(Duration::from_millis(1));
+        loop {
+            match events.recv_timeout(TIMEOUT).unwrap() {
+                SessionEvent::Error(error::RecvError::NetworkError(_)) => (),
+                SessionEvent::Error(e) => panic!("Unexpected error {e}"),
+                SessionEvent::Stopped => break,
+            }
+        }
+        // The dead connection is not pinged again
+        assert!(next_sent(&sent, "ping", QUIET).is_none());
+
+        session.close();
+    }
+
+    #[test]
+    fn delays_frames_over_send_rate() {
+        let (credentials, _) = serve(Vec::new(), true);
+        let clock = Arc::new(ManualClock::new());
+        let (sent_send, sent) = mpsc::channel();
+        let options = Options {
+            send_rate: Some((2, Duration::from_secs(1))),
+            ..manual_options(&clock, sent_send)
+        };
+
+        let (session, _) = Session::connect_with(&credentials, options).unwrap();
+        let sender = session.clone();
+        let sending = thread::spawn(move || {
+            for id in 0..3 {
+                let frame = serde_json::json!({"id": id, "type": "raw"});
+                sender.send_raw(frame).unwrap();
+            }
+        });
+
+        assert!(next_sent(&sent, "raw", TIMEOUT).is_some());
+        assert!(next_sent(&sent, "raw", TIMEOUT).is_some());
+        assert!(next_sent(&sent, "raw", QUIET).is_none());
+
+        // The bucket refills at 2 frames a second
+        clock.advance(Duration::from_millis(499));
+        assert!(next_sent(&sent, "raw", QUIET).is_none());
+        clock.advance(Duration::from_millis(1));
+        assert!(next_sent(&sent, "raw", TIMEOUT).is_some());
+
+        sending.join().unwrap();
+        session.close();
+    }
+
+    #[test]
+    fn refuses_second_route_for_topic() {
+        let frames = vec![tungstenite::Message::Text(level2_message(1))];
+        let (credentials, _) = serve(frames, true);
+
+        let (session, _) = Session::connect_with(&credentials, Options::default()).unwrap();
+        let books = session.subscribe_level2(SYMBOL).unwrap();
+
+        assert!(matches!(
+            session.subscribe_level2(SYMBOL),
+            Err(KucoinError::AlreadySubscribed { .. })
+        ));
+        assert!(matches!(
+            session.register_topic(&Topic::level2_depth5(SYMBOL)),
+            Err(KucoinError::AlreadySubscribed { .. })
+        ));
+        assert_eq!(session.subscription_count(), 1);
+        assert!(books.recv_timeout(TIMEOUT).is_ok());
+
+        session.close();
+    }
+
+    #[test]
+    fn refuses_symbol_listed_twice() {
+        let (credentials, _) = serve(Vec::new(), true);
+        let clock = Arc::new(ManualClock::new());
+        let (sent_send, sent) = mpsc::channel();
+
+        let options = manual_options(&clock, sent_send);
+        let (session, _) = Session::connect_with(&credentials, options).unwrap();
+
+        assert!(matches!(
+            session.subscribe_level2_many(&["ETHUSDTM", SYMBOL, SYMBOL]),
+            Err(KucoinError::AlreadySubscribed { .. })
+        ));
+        assert_eq!(session.subscription_count(), 0);
+        // Only pings, no subscribing and unsubscribing again
+        thread::sleep(QUIET);
+        assert!(sent
+            .try_iter()
+            .all(|frame| frame.contains(r#""type":"ping""#)));
+
+        session.close();
+    }
+
+    // Hands out the credentials it holds, whatever the current ones
+    struct FixedTokenProvider(Credentials);
+
+    impl TokenProvider for FixedTokenProvider {
+        fn fetch(&self, _: &Credentials) -> Result<Credentials, KucoinError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn rotates_token_without_losing_connection() {
+        let (credentials, _) = serve(Vec::new(), true);
+        let frames = vec![tungstenite::Message::Text(level2_message(2))];
+        let (rotated, _) = serve(frames, true);
+        let (events_send, events) = mpsc::channel();
+        let options = Options {
+            reconnect: None,
+            session_events: Some(events_send),
+            token_provider: Arc::new(FixedTokenProvider(rotated.clone())),
+            ..Options::default()
+        };
 
-        let (send, recv) = mpsc::channel::<book::MarketBook>();
-        self.data.lock().unwrap().insert(topic, send);
+        let (session, _) = Session::connect_with(&credentials, options).unwrap();
+        session.rotate_token().unwrap();
+        assert_eq!(session.endpoint(), rotated.endpoint());
 
-        self.send(msg).expect("Subscribe failed");
-        // todo: ack
+        // Still receiving, from the new connection only
+        let books = session.subscribe_level2(SYMBOL).unwrap();
+        assert!(books.recv_timeout(TIMEOUT).is_ok());
+        assert!(events.recv_timeout(QUIET).is_err());
+        assert_eq!(session.reconnect_count(), 1);
 
-        recv
+        session.close();
     }
 }
@@ -0,0 +1,89 @@
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// How long a sleeper on a `ManualClock` waits for it to be advanced before
+// waking up anyway
+const MANUAL_SLEEP_SLICE: Duration = Duration::from_millis(10);
+
+/// Source of time for the session's time-based logic: pings, throttling
+/// and timestamps.
+///
+/// Swapping in a `ManualClock` makes that logic testable without real sleeps.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn system_now(&self) -> SystemTime;
+    /// Sleeps for up to `duration`. May wake up early; callers check `now`
+    /// to find out how long is left.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// A clock that only moves when advanced. Sleeping waits for it to be
+/// advanced past the deadline, waking up early every few milliseconds so
+/// that sleepers can tell when e.g. their session was closed.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<(Instant, SystemTime)>,
+    advanced: Condvar,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            now: Mutex::new((Instant::now(), SystemTime::now())),
+            advanced: Condvar::new(),
+        }
+    }
+
+    /// Moves the clock forward by `duration`, waking up the sleepers whose
+    /// deadline has passed.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        now.0 += duration;
+        now.1 += duration;
+        self.advanced.notify_all();
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.lock().unwrap().0
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.now.lock().unwrap().1
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let now = self.now.lock().unwrap();
+        let deadline = now.0 + duration;
+        let _ = self
+            .advanced
+            .wait_timeout_while(now, MANUAL_SLEEP_SLICE, |now| now.0 < deadline)
+            .unwrap();
+    }
+}
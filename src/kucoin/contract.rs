@@ -0,0 +1,17 @@
+use serde::Deserialize;
+
+/// Trading rules of a futures contract, as listed by KuCoin's contracts API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractInfo {
+    pub symbol: String,
+    /// Quantity of the base asset one contract stands for. Negative for
+    /// inverse contracts.
+    pub multiplier: f64,
+    /// Minimum price increment.
+    pub tick_size: f64,
+    /// Minimum order size, in contracts.
+    pub lot_size: i64,
+    /// Highest leverage positions in the contract may take.
+    pub max_leverage: f64,
+}
@@ -0,0 +1,47 @@
+use super::book::{Level, MarketBook, Side};
+use super::error::RecvError;
+use rust_decimal::Decimal;
+
+/// A price level of the book with its price as a `Decimal`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalLevel {
+    pub price: Decimal,
+    pub size: i64,
+}
+
+impl Level {
+    /// The price as a `Decimal`, parsed from the shortest text the f64
+    /// prints as. Prefer `MarketBook::decimal_levels`, which uses the text
+    /// KuCoin sent.
+    pub fn decimal(&self) -> Result<DecimalLevel, RecvError> {
+        let price = Decimal::from_str_exact(&self.price.to_string()).map_err(|_| {
+            RecvError::InvalidFormat(format!("Price {} is not a decimal", self.price))
+        })?;
+        Ok(DecimalLevel {
+            price,
+            size: self.size,
+        })
+    }
+}
+
+impl MarketBook {
+    /// Levels present on `side`, best price first, with prices as `Decimal`
+    /// for computations that must not accumulate floating point error.
+    ///
+    /// Prices of books parsed from a message are exactly the text KuCoin
+    /// sent, unless `Decimal` cannot parse it exactly, e.g. in exponent
+    /// notation, which is converted from the f64. Other books, e.g. built
+    /// from levels or loaded back, convert their f64 prices as
+    /// `Level::decimal` does.
+    pub fn decimal_levels(&self, side: Side) -> Result<Vec<DecimalLevel>, RecvError> {
+        self.levels(side)
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, size))| *size > 0)
+            .map(|(n, &(price, size))| match self.sent_price(side, n) {
+                Some(price) => Ok(DecimalLevel { price, size }),
+                None => Level { price, size }.decimal(),
+            })
+            .collect()
+    }
+}
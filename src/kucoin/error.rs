@@ -1,11 +1,26 @@
 use serde_json;
+use std::time::Duration;
+use thiserror::Error;
 use tungstenite;
 
-#[derive(Debug)]
+/// Failure to receive or make sense of a single message.
+#[derive(Debug, Error)]
 pub enum RecvError {
+    #[error("missing key: {0}")]
     KeyNotExists(String),
-    ParseError(serde_json::Error),
-    NetworkError(tungstenite::Error)
+    #[error("invalid format: {0}")]
+    InvalidFormat(String),
+    #[error("cannot parse message: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("network error: {0}")]
+    NetworkError(#[source] Box<tungstenite::Error>),
+}
+
+// tungstenite::Error is large, boxing it keeps every Result small
+impl From<tungstenite::Error> for RecvError {
+    fn from(value: tungstenite::Error) -> Self {
+        RecvError::NetworkError(Box::new(value))
+    }
 }
 
 impl From<String> for RecvError {
@@ -14,14 +29,80 @@ impl From<String> for RecvError {
     }
 }
 
-impl From<serde_json::Error> for RecvError {
-    fn from(value: serde_json::Error) -> Self {
-        RecvError::ParseError(value)
-    }
+/// Every error returned by `Credentials`, `Session` and the types built on
+/// them.
+#[derive(Debug, Error)]
+pub enum KucoinError {
+    /// The WebSocket connection failed
+    #[error("WebSocket error: {0}")]
+    WebSocket(#[source] Box<tungstenite::Error>),
+    /// A request to KuCoin's HTTPS API failed
+    #[error("request to KuCoin's API failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// KuCoin's API handed out a token that cannot be used
+    #[error("unusable token: {0}")]
+    Token(String),
+    /// A message or response could not be parsed
+    #[error("cannot parse message: {0}")]
+    Parse(RecvError),
+    /// KuCoin's REST API refused a request
+    #[error("KuCoin's API answered {code}: {msg}")]
+    Api { code: String, msg: String },
+    /// A REST request would exceed KuCoin's rate limits, or KuCoin said it
+    /// did
+    #[error("rate limited, retry in {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+    /// A private REST endpoint was called without an API key
+    #[error("request to {endpoint} needs an API key")]
+    NoApiKey { endpoint: String },
+    /// An environment variable holds an unusable value
+    #[error("environment variable {name} {reason}")]
+    Env { name: &'static str, reason: String },
+    /// The server answered with something this client does not expect
+    #[error("unexpected response: {0}")]
+    Protocol(String),
+    /// The server answered a subscription with an error frame
+    #[error("subscription to {symbol} rejected: {reason}")]
+    SubscriptionRejected { symbol: String, reason: String },
+    /// The server did not acknowledge a subscription in time
+    #[error("subscription to {symbol} was not acknowledged in time")]
+    SubscriptionTimeout { symbol: String },
+    /// No book was received in time
+    #[error("no book received for {symbol} in time")]
+    BookTimeout { symbol: String },
+    /// The session stopped delivering messages
+    #[error("channel for {symbol} was closed")]
+    ChannelClosed { symbol: String },
+    /// No TLS backend is available to reach KuCoin's HTTPS API
+    #[error(
+        "cannot build an HTTPS client ({0}), make sure reqwest is built with its native-tls feature"
+    )]
+    TlsUnavailable(reqwest::Error),
+    /// Subscribing would exceed the session's subscription limit
+    #[error("session is limited to {max} subscriptions")]
+    TooManySubscriptions { max: usize },
+    /// The session already routes the messages of the topic to another
+    /// consumer
+    #[error("already subscribed to {topic}")]
+    AlreadySubscribed { topic: String },
+    /// KuCoin handed out a token without any server to connect to
+    #[error("KuCoin returned no instance servers, it may be under maintenance")]
+    NoInstanceServers,
 }
 
-impl From<tungstenite::Error> for RecvError {
+impl From<tungstenite::Error> for KucoinError {
     fn from(value: tungstenite::Error) -> Self {
-        RecvError::NetworkError(value)
+        KucoinError::WebSocket(Box::new(value))
+    }
+}
+
+impl From<RecvError> for KucoinError {
+    // Network failures met while receiving are failures of the connection,
+    // not of the message
+    fn from(value: RecvError) -> Self {
+        match value {
+            RecvError::NetworkError(e) => KucoinError::WebSocket(e),
+            other => KucoinError::Parse(other),
+        }
     }
 }
@@ -0,0 +1,13 @@
+use super::error::RecvError;
+
+/// Something the receiving thread of a session ran into, as delivered to
+/// `SessionBuilder::session_events`.
+#[derive(Debug)]
+pub enum SessionEvent {
+    /// A message could not be received, parsed or routed. The session keeps
+    /// receiving after it.
+    Error(RecvError),
+    /// The connection was lost and could not be replaced, so the session
+    /// stopped receiving. Not sent when the session is closed.
+    Stopped,
+}
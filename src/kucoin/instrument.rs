@@ -0,0 +1,135 @@
+use super::error::RecvError;
+use super::json::de;
+use serde::Deserialize;
+
+/// Mark price of a futures contract, the price liquidations are based on.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MarkPrice {
+    #[serde(rename = "markPrice", deserialize_with = "de::float")]
+    pub price: f64,
+    /// Period the price is computed over, in milliseconds.
+    #[serde(deserialize_with = "de::integer")]
+    pub granularity: u64,
+    /// When the price was computed, in milliseconds since the epoch.
+    #[serde(rename = "timePoint", deserialize_with = "de::integer")]
+    pub ts: u64,
+}
+
+/// Index price of a futures contract, tracking the spot price of its
+/// underlying.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct IndexPrice {
+    #[serde(rename = "indexPrice", deserialize_with = "de::float")]
+    pub price: f64,
+    /// Period the price is computed over, in milliseconds.
+    #[serde(deserialize_with = "de::integer")]
+    pub granularity: u64,
+    /// When the price was computed, in milliseconds since the epoch.
+    #[serde(rename = "timePoint", deserialize_with = "de::integer")]
+    pub ts: u64,
+}
+
+/// Funding rate of a futures contract.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FundingRate {
+    #[serde(rename = "fundingRate", deserialize_with = "de::float")]
+    pub rate: f64,
+    /// Period the rate is computed over, in milliseconds.
+    #[serde(deserialize_with = "de::integer")]
+    pub granularity: u64,
+    /// When the rate was computed, in milliseconds since the epoch.
+    #[serde(rename = "timePoint", deserialize_with = "de::integer")]
+    pub ts: u64,
+}
+
+/// An update of the instrument topic of a futures contract.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstrumentEvent {
+    MarkPrice(MarkPrice),
+    IndexPrice(IndexPrice),
+    FundingRate(FundingRate),
+}
+
+impl InstrumentEvent {
+    /// Parses the message text of an instrument topic. Mark and index prices
+    /// come in the same message, so it may hold more than one event.
+    pub(super) fn parse(text: &str) -> Result<Vec<Self>, RecvError> {
+        let events = match serde_json::from_str(text)? {
+            RawInstrument::MarkIndexPrice(price) => vec![
+                InstrumentEvent::MarkPrice(MarkPrice {
+                    price: price.mark_price,
+                    granularity: price.granularity,
+                    ts: price.time_point,
+                }),
+                InstrumentEvent::IndexPrice(IndexPrice {
+                    price: price.index_price,
+                    granularity: price.granularity,
+                    ts: price.time_point,
+                }),
+            ],
+            RawInstrument::FundingRate(rate) => vec![InstrumentEvent::FundingRate(rate)],
+        };
+
+        Ok(events)
+    }
+}
+
+// A message of an instrument topic
+#[derive(Deserialize)]
+#[serde(tag = "subject", content = "data")]
+enum RawInstrument {
+    #[serde(rename = "mark.index.price")]
+    MarkIndexPrice(RawMarkIndexPrice),
+    #[serde(rename = "funding.rate")]
+    FundingRate(FundingRate),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawMarkIndexPrice {
+    #[serde(deserialize_with = "de::float")]
+    mark_price: f64,
+    #[serde(deserialize_with = "de::float")]
+    index_price: f64,
+    #[serde(deserialize_with = "de::integer")]
+    granularity: u64,
+    #[serde(deserialize_with = "de::integer")]
+    time_point: u64,
+}
+
+/// A funding settlement of a futures contract.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FundingSettlement {
+    pub symbol: String,
+    /// Funding rate being settled.
+    #[serde(rename = "fundingRate", deserialize_with = "de::float")]
+    pub rate: f64,
+    /// When funding is settled, in milliseconds since the epoch.
+    #[serde(rename = "fundingTime", deserialize_with = "de::integer")]
+    pub funding_time: u64,
+    /// When the announcement was sent, in milliseconds since the epoch.
+    #[serde(rename = "timestamp", deserialize_with = "de::integer")]
+    pub ts: u64,
+}
+
+/// A message of the announcement topic, shared by every futures contract.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "subject", content = "data")]
+pub enum Announcement {
+    #[serde(rename = "funding.begin")]
+    FundingBegin(FundingSettlement),
+    #[serde(rename = "funding.end")]
+    FundingEnd(FundingSettlement),
+}
+
+impl Announcement {
+    /// Parses the data message of the announcement topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        Ok(Announcement::deserialize(msg)?)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        Ok(serde_json::from_str(text)?)
+    }
+}
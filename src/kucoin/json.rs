@@ -0,0 +1,144 @@
+use super::error::RecvError;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+// Readers of the fields of data messages. Most topics send numbers as
+// strings, some as JSON numbers, and some mix both within one message.
+
+/// The `data` of a message.
+pub(super) fn data(msg: &serde_json::Value) -> Result<&serde_json::Value, RecvError> {
+    msg.get("data")
+        .ok_or_else(|| RecvError::KeyNotExists("key data not exists".into()))
+}
+
+pub(super) fn float(value: &serde_json::Value) -> Result<f64, RecvError> {
+    match value {
+        serde_json::Value::String(s) => s.parse::<f64>().ok(),
+        other => other.as_f64(),
+    }
+    .ok_or_else(|| RecvError::InvalidFormat(format!("{value} is not a float")))
+}
+
+pub(super) fn integer(value: &serde_json::Value) -> Result<u64, RecvError> {
+    match value {
+        serde_json::Value::String(s) => s.parse::<u64>().ok(),
+        other => other.as_u64(),
+    }
+    .ok_or_else(|| RecvError::InvalidFormat(format!("{value} is not an integer")))
+}
+
+/// A size in contracts, negative for short positions.
+pub(super) fn size(value: &serde_json::Value) -> Result<i64, RecvError> {
+    match value {
+        serde_json::Value::String(s) => s.parse::<i64>().ok(),
+        other => other.as_i64(),
+    }
+    .ok_or_else(|| RecvError::InvalidFormat(format!("{value} is not an integer")))
+}
+
+pub(super) fn string(value: &serde_json::Value) -> Result<String, RecvError> {
+    value
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| RecvError::InvalidFormat(format!("{value} is not a string")))
+}
+
+/// A field that may be missing, null or empty.
+pub(super) fn optional<T>(
+    value: &serde_json::Value,
+    read: impl Fn(&serde_json::Value) -> Result<T, RecvError>,
+) -> Result<Option<T>, RecvError> {
+    match value {
+        serde_json::Value::Null => Ok(None),
+        serde_json::Value::String(s) if s.is_empty() => Ok(None),
+        other => read(other).map(Some),
+    }
+}
+
+/// The `data` of a message, deserialized into `T`.
+pub(super) fn payload<T: DeserializeOwned>(msg: &serde_json::Value) -> Result<T, RecvError> {
+    Ok(T::deserialize(data(msg)?)?)
+}
+
+/// Same as `payload`, parsing the message text directly.
+pub(super) fn payload_from_str<'a, T: Deserialize<'a>>(text: &'a str) -> Result<T, RecvError> {
+    let msg: Data<T> = serde_json::from_str(text)?;
+    Ok(msg.data)
+}
+
+// A message of which only the `data` is read
+#[derive(Deserialize)]
+struct Data<T> {
+    data: T,
+}
+
+// The readers above for `deserialize_with`, on payloads deriving
+// `Deserialize`
+pub(super) mod de {
+    use super::RecvError;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn float<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        read(deserializer, super::float)
+    }
+
+    pub fn integer<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        read(deserializer, super::integer)
+    }
+
+    // An array of floats, each read like `float`
+    pub fn floats<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f64>, D::Error> {
+        read(deserializer, |value| match value {
+            serde_json::Value::Array(values) => values.iter().map(super::float).collect(),
+            other => Err(RecvError::InvalidFormat(format!("{other} is not an array"))),
+        })
+    }
+
+    pub fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        read(deserializer, super::size)
+    }
+
+    // The readers below go with `#[serde(default)]`, for fields that may
+    // also be missing
+
+    pub fn optional_float<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<f64>, D::Error> {
+        read(deserializer, |value| super::optional(value, super::float))
+    }
+
+    pub fn optional_integer<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        read(deserializer, |value| super::optional(value, super::integer))
+    }
+
+    pub fn optional_size<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<i64>, D::Error> {
+        read(deserializer, |value| super::optional(value, super::size))
+    }
+
+    pub fn optional_string<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<String>, D::Error> {
+        read(deserializer, |value| super::optional(value, super::string))
+    }
+
+    // A limit price, `None` for market orders, which carry no price or a
+    // price of 0
+    pub fn limit_price<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<f64>, D::Error> {
+        Ok(optional_float(deserializer)?.filter(|price| *price != 0.0))
+    }
+
+    fn read<'de, D: Deserializer<'de>, T>(
+        deserializer: D,
+        read: fn(&serde_json::Value) -> Result<T, RecvError>,
+    ) -> Result<T, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        read(&value).map_err(D::Error::custom)
+    }
+}
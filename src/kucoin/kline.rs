@@ -0,0 +1,139 @@
+use super::error::RecvError;
+use super::json::{de, payload, payload_from_str};
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+/// Length of the candles of a kline topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KlineInterval {
+    OneMinute,
+    ThreeMinutes,
+    FifteenMinutes,
+    ThirtyMinutes,
+    OneHour,
+    TwoHours,
+    FourHours,
+    EightHours,
+    TwelveHours,
+    OneDay,
+    OneWeek,
+}
+
+impl KlineInterval {
+    /// The interval as KuCoin names it in topics, e.g. `1min`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KlineInterval::OneMinute => "1min",
+            KlineInterval::ThreeMinutes => "3min",
+            KlineInterval::FifteenMinutes => "15min",
+            KlineInterval::ThirtyMinutes => "30min",
+            KlineInterval::OneHour => "1hour",
+            KlineInterval::TwoHours => "2hour",
+            KlineInterval::FourHours => "4hour",
+            KlineInterval::EightHours => "8hour",
+            KlineInterval::TwelveHours => "12hour",
+            KlineInterval::OneDay => "1day",
+            KlineInterval::OneWeek => "1week",
+        }
+    }
+
+    /// Length of the interval in minutes, as the REST API takes it.
+    pub fn minutes(self) -> u64 {
+        match self {
+            KlineInterval::OneMinute => 1,
+            KlineInterval::ThreeMinutes => 3,
+            KlineInterval::FifteenMinutes => 15,
+            KlineInterval::ThirtyMinutes => 30,
+            KlineInterval::OneHour => 60,
+            KlineInterval::TwoHours => 120,
+            KlineInterval::FourHours => 240,
+            KlineInterval::EightHours => 480,
+            KlineInterval::TwelveHours => 720,
+            KlineInterval::OneDay => 1440,
+            KlineInterval::OneWeek => 10080,
+        }
+    }
+
+    /// Length of the interval.
+    pub fn duration(self) -> Duration {
+        Duration::from_secs(self.minutes() * 60)
+    }
+}
+
+impl Display for KlineInterval {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A candle of a futures contract, updated as trades come in until its
+/// interval ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candle {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    /// Start of the candle, in seconds since the epoch.
+    pub ts: u64,
+}
+
+impl Candle {
+    /// Parses the data message of a kline topic, whose candle is sent as
+    /// `[time, open, close, high, low, volume, turnover]`.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        Candle::from_raw(payload(msg)?)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        Candle::from_raw(payload_from_str(text)?)
+    }
+
+    fn from_raw(raw: RawCandle) -> Result<Self, RecvError> {
+        let &[time, open, close, high, low, volume, ..] = raw.candles.as_slice() else {
+            return Err(RecvError::InvalidFormat(format!(
+                "Candle {:?} has fewer than 6 fields",
+                raw.candles
+            )));
+        };
+
+        Ok(Candle {
+            open,
+            close,
+            high,
+            low,
+            volume,
+            ts: time as u64,
+        })
+    }
+
+    /// Parses a candle of the REST kline API, sent as
+    /// `[time in ms, open, high, low, close, volume]`.
+    pub(super) fn from_rest(candle: &[f64]) -> Result<Self, RecvError> {
+        let &[time, open, high, low, close, volume, ..] = candle else {
+            return Err(RecvError::InvalidFormat(format!(
+                "Candle {candle:?} has fewer than 6 fields"
+            )));
+        };
+
+        Ok(Candle {
+            open,
+            high,
+            low,
+            close,
+            volume,
+            ts: time as u64 / 1000,
+        })
+    }
+}
+
+// The data message of a kline topic. Its time, in seconds, is read as a
+// float like the other fields, which holds it exactly.
+#[derive(Deserialize)]
+struct RawCandle {
+    #[serde(deserialize_with = "de::floats")]
+    candles: Vec<f64>,
+}
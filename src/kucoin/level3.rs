@@ -0,0 +1,250 @@
+use super::book::{Level, Price, Side};
+use super::error::RecvError;
+use super::json::de;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Why an order left the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DoneReason {
+    Filled,
+    Canceled,
+}
+
+/// A message of the level 3 feed of a symbol, following a single order
+/// through the matching engine.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(
+    tag = "subject",
+    content = "data",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Level3Event {
+    /// The matching engine accepted an order.
+    Received {
+        #[serde(deserialize_with = "de::integer")]
+        sequence: u64,
+        order_id: String,
+        #[serde(deserialize_with = "de::integer")]
+        ts: u64,
+    },
+    /// An order started resting on the book.
+    Open {
+        #[serde(deserialize_with = "de::integer")]
+        sequence: u64,
+        order_id: String,
+        side: Side,
+        #[serde(deserialize_with = "de::float")]
+        price: f64,
+        #[serde(deserialize_with = "de::size")]
+        size: i64,
+        #[serde(deserialize_with = "de::integer")]
+        ts: u64,
+    },
+    /// The size of a resting order changed.
+    Update {
+        #[serde(deserialize_with = "de::integer")]
+        sequence: u64,
+        order_id: String,
+        #[serde(deserialize_with = "de::size")]
+        size: i64,
+        #[serde(deserialize_with = "de::integer")]
+        ts: u64,
+    },
+    /// A taker order traded against a resting maker order.
+    Match {
+        #[serde(deserialize_with = "de::integer")]
+        sequence: u64,
+        maker_order_id: String,
+        taker_order_id: String,
+        trade_id: String,
+        /// Side of the taker.
+        side: Side,
+        #[serde(deserialize_with = "de::float")]
+        price: f64,
+        #[serde(deserialize_with = "de::size")]
+        size: i64,
+        #[serde(deserialize_with = "de::integer")]
+        ts: u64,
+    },
+    /// An order left the book.
+    Done {
+        #[serde(deserialize_with = "de::integer")]
+        sequence: u64,
+        order_id: String,
+        reason: DoneReason,
+        #[serde(deserialize_with = "de::integer")]
+        ts: u64,
+    },
+}
+
+impl Level3Event {
+    /// Parses the data message of a level 3 topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        Ok(Level3Event::deserialize(msg)?)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    pub fn sequence(&self) -> u64 {
+        match self {
+            Level3Event::Received { sequence, .. }
+            | Level3Event::Open { sequence, .. }
+            | Level3Event::Update { sequence, .. }
+            | Level3Event::Match { sequence, .. }
+            | Level3Event::Done { sequence, .. } => *sequence,
+        }
+    }
+}
+
+/// An order resting on a `Level3Book`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestingOrder {
+    pub side: Side,
+    pub price: f64,
+    pub size: i64,
+}
+
+/// Every resting order of a symbol, rebuilt from its `Level3Event`s.
+///
+/// Orders queue at their price in the order they opened, so the book can
+/// tell how much rests ahead of any order. Only orders opened after the
+/// book started following the feed are known.
+#[derive(Debug, Clone, Default)]
+pub struct Level3Book {
+    sequence: u64,
+    orders: HashMap<String, RestingOrder>,
+    asks: BTreeMap<Price, VecDeque<String>>,
+    bids: BTreeMap<Reverse<Price>, VecDeque<String>>,
+}
+
+impl Level3Book {
+    /// Sequence of the last event applied.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Updates the book with the next event of the feed.
+    pub fn apply(&mut self, event: &Level3Event) {
+        self.sequence = event.sequence();
+
+        match event {
+            Level3Event::Received { .. } => (),
+            Level3Event::Open {
+                order_id,
+                side,
+                price,
+                size,
+                ..
+            } => {
+                self.queue(*side, *price).push_back(order_id.clone());
+                let order = RestingOrder {
+                    side: *side,
+                    price: *price,
+                    size: *size,
+                };
+                self.orders.insert(order_id.clone(), order);
+            }
+            Level3Event::Update { order_id, size, .. } => {
+                if let Some(order) = self.orders.get_mut(order_id) {
+                    order.size = *size;
+                }
+            }
+            Level3Event::Match {
+                maker_order_id,
+                size,
+                ..
+            } => {
+                if let Some(order) = self.orders.get_mut(maker_order_id) {
+                    order.size -= size;
+                }
+            }
+            Level3Event::Done { order_id, .. } => self.remove(order_id),
+        }
+    }
+
+    fn queue(&mut self, side: Side, price: f64) -> &mut VecDeque<String> {
+        match side {
+            Side::Buy => self.bids.entry(Reverse(Price(price))).or_default(),
+            Side::Sell => self.asks.entry(Price(price)).or_default(),
+        }
+    }
+
+    fn remove(&mut self, order_id: &str) {
+        let Some(order) = self.orders.remove(order_id) else {
+            return;
+        };
+
+        let queue = self.queue(order.side, order.price);
+        queue.retain(|id| id != order_id);
+        if queue.is_empty() {
+            match order.side {
+                Side::Buy => self.bids.remove(&Reverse(Price(order.price))),
+                Side::Sell => self.asks.remove(&Price(order.price)),
+            };
+        }
+    }
+
+    pub fn order(&self, order_id: &str) -> Option<&RestingOrder> {
+        self.orders.get(order_id)
+    }
+
+    /// Number of orders resting on the book.
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    /// Orders queued ahead of `order_id` at its price, and their total size.
+    pub fn queue_position(&self, order_id: &str) -> Option<(usize, i64)> {
+        let order = self.orders.get(order_id)?;
+        let queue = match order.side {
+            Side::Buy => self.bids.get(&Reverse(Price(order.price))),
+            Side::Sell => self.asks.get(&Price(order.price)),
+        }?;
+
+        let ahead = queue.iter().take_while(|id| *id != order_id);
+        let (count, size) = ahead.fold((0, 0), |(count, size), id| {
+            (
+                count + 1,
+                size + self.orders.get(id).map_or(0, |order| order.size),
+            )
+        });
+        Some((count, size))
+    }
+
+    // Total size of a queue of orders
+    fn level(&self, price: f64, queue: &VecDeque<String>) -> Level {
+        let size = queue
+            .iter()
+            .filter_map(|id| self.orders.get(id))
+            .map(|order| order.size);
+        Level {
+            price,
+            size: size.sum(),
+        }
+    }
+
+    /// Ask levels, lowest price first, each summing the orders at its price.
+    pub fn asks_iter(&self) -> impl Iterator<Item = Level> + '_ {
+        self.asks
+            .iter()
+            .map(|(&Price(price), queue)| self.level(price, queue))
+    }
+
+    /// Bid levels, highest price first, each summing the orders at its price.
+    pub fn bids_iter(&self) -> impl Iterator<Item = Level> + '_ {
+        self.bids
+            .iter()
+            .map(|(&Reverse(Price(price)), queue)| self.level(price, queue))
+    }
+}
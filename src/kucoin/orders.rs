@@ -0,0 +1,391 @@
+use super::book::Side;
+use super::error::RecvError;
+use super::json::{de, payload, payload_from_str};
+use serde::{Deserialize, Serialize};
+
+/// What happened to an order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderEventKind {
+    /// The order started resting on the book.
+    Open,
+    /// The order traded, partially or in full.
+    Match,
+    /// The order was filled in full and left the book.
+    Filled,
+    /// The order was canceled and left the book.
+    Canceled,
+    /// The size of the order was changed.
+    Update,
+}
+
+/// A change to one of the account's own orders, as sent on the private
+/// `/contractMarket/tradeOrders` topic.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderUpdate {
+    pub order_id: String,
+    #[serde(default, deserialize_with = "de::optional_string")]
+    pub client_oid: Option<String>,
+    pub symbol: String,
+    #[serde(rename = "type")]
+    pub kind: OrderEventKind,
+    pub side: Side,
+    /// Limit price, `None` for market orders.
+    #[serde(default, deserialize_with = "de::limit_price")]
+    pub price: Option<f64>,
+    /// Size of the order, in contracts.
+    #[serde(deserialize_with = "de::size")]
+    pub size: i64,
+    #[serde(deserialize_with = "de::size")]
+    pub filled_size: i64,
+    #[serde(deserialize_with = "de::size")]
+    pub remain_size: i64,
+    /// Price and size of the trade, for `OrderEventKind::Match`.
+    #[serde(default, deserialize_with = "de::optional_float")]
+    pub match_price: Option<f64>,
+    #[serde(default, deserialize_with = "de::optional_size")]
+    pub match_size: Option<i64>,
+    #[serde(default, deserialize_with = "de::optional_string")]
+    pub trade_id: Option<String>,
+    /// When the change happened, in nanoseconds since the epoch.
+    #[serde(deserialize_with = "de::integer")]
+    pub ts: u64,
+}
+
+impl OrderUpdate {
+    /// Parses the data message of the trade orders topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        payload(msg)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        payload_from_str(text)
+    }
+}
+
+/// What happened to a stop order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopOrderEventKind {
+    /// The stop order was placed and waits for its stop price.
+    Open,
+    /// The stop price was reached and the order was sent to the book.
+    Triggered,
+    /// The stop order was canceled before triggering.
+    #[serde(rename = "cancel")]
+    Canceled,
+}
+
+/// Which way the price must cross the stop price to trigger a stop order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopDirection {
+    Up,
+    Down,
+}
+
+/// A change to one of the account's own stop orders, as sent on the private
+/// `/contractMarket/advancedOrders` topic.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StopOrderUpdate {
+    pub order_id: String,
+    pub symbol: String,
+    #[serde(rename = "type")]
+    pub kind: StopOrderEventKind,
+    pub side: Side,
+    /// Size of the order, in contracts.
+    #[serde(deserialize_with = "de::size")]
+    pub size: i64,
+    /// Limit price of the order sent once triggered, `None` for market
+    /// orders.
+    #[serde(default, deserialize_with = "de::limit_price")]
+    pub order_price: Option<f64>,
+    #[serde(deserialize_with = "de::float")]
+    pub stop_price: f64,
+    #[serde(rename = "stop")]
+    pub direction: StopDirection,
+    /// Whether the triggered order was placed, for
+    /// `StopOrderEventKind::Triggered`.
+    #[serde(default)]
+    pub trigger_success: Option<bool>,
+    /// Why the triggered order could not be placed.
+    #[serde(default, deserialize_with = "de::optional_string")]
+    pub error: Option<String>,
+    /// When the change happened, in milliseconds since the epoch.
+    #[serde(deserialize_with = "de::integer")]
+    pub ts: u64,
+}
+
+impl StopOrderUpdate {
+    /// Parses the data message of the advanced orders topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        payload(msg)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        payload_from_str(text)
+    }
+}
+
+/// How long an order rests on the book before it is canceled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum TimeInForce {
+    /// Good till canceled.
+    #[default]
+    #[serde(rename = "GTC")]
+    GoodTillCanceled,
+    /// Immediate or cancel: whatever does not fill at once is canceled.
+    #[serde(rename = "IOC")]
+    ImmediateOrCancel,
+}
+
+/// Whether an order rests on the book at a price or takes what is there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderType {
+    Limit,
+    Market,
+}
+
+/// An order to place through `RestClient::place_order`.
+///
+/// # Usage
+/// ```ignore
+/// let order = OrderRequest::limit("XBTUSDTM", Side::Buy, 30000.0, 1)
+///     .leverage(5)
+///     .time_in_force(TimeInForce::ImmediateOrCancel);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderRequest {
+    client_oid: String,
+    symbol: String,
+    side: Side,
+    #[serde(rename = "type")]
+    order_type: OrderType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<String>,
+    size: i64,
+    leverage: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_in_force: Option<TimeInForce>,
+    reduce_only: bool,
+}
+
+// Leverage of orders unless set, which KuCoin requires for every order
+const DEFAULT_LEVERAGE: u32 = 1;
+
+impl OrderRequest {
+    /// A limit order of `size` contracts of `symbol` at `price`.
+    pub fn limit(symbol: &str, side: Side, price: f64, size: i64) -> Self {
+        OrderRequest {
+            order_type: OrderType::Limit,
+            price: Some(price.to_string()),
+            time_in_force: Some(TimeInForce::default()),
+            ..OrderRequest::market(symbol, side, size)
+        }
+    }
+
+    /// A market order of `size` contracts of `symbol`.
+    pub fn market(symbol: &str, side: Side, size: i64) -> Self {
+        OrderRequest {
+            client_oid: format!("{:032x}", rand::random::<u128>()),
+            symbol: symbol.to_owned(),
+            side,
+            order_type: OrderType::Market,
+            price: None,
+            size,
+            leverage: DEFAULT_LEVERAGE,
+            time_in_force: None,
+            reduce_only: false,
+        }
+    }
+
+    /// Leverage of the position the order opens, 1 unless set.
+    pub fn leverage(mut self, leverage: u32) -> Self {
+        self.leverage = leverage;
+        self
+    }
+
+    /// How long a limit order rests on the book, good till canceled unless
+    /// set. Ignored for market orders.
+    pub fn time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        if self.order_type == OrderType::Limit {
+            self.time_in_force = Some(time_in_force);
+        }
+        self
+    }
+
+    /// Only let the order reduce the position, never open or grow one.
+    pub fn reduce_only(mut self, reduce_only: bool) -> Self {
+        self.reduce_only = reduce_only;
+        self
+    }
+
+    /// Identifies the order in `OrderUpdate`s; a random one is generated
+    /// unless set.
+    pub fn client_oid(mut self, client_oid: &str) -> Self {
+        self.client_oid = client_oid.to_owned();
+        self
+    }
+}
+
+/// An order KuCoin accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlacedOrder {
+    pub order_id: String,
+    pub client_oid: String,
+}
+
+/// Orders KuCoin canceled.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CanceledOrders {
+    /// Ids of the canceled orders, empty if there was nothing to cancel.
+    #[serde(rename = "cancelledOrderIds", default)]
+    pub order_ids: Vec<String>,
+}
+
+/// An order KuCoin canceled, looked up by its client id.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanceledClientOrder {
+    pub client_oid: String,
+}
+
+/// An order of the account, as listed by KuCoin's orders API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Order {
+    #[serde(rename = "id")]
+    pub order_id: String,
+    #[serde(default, deserialize_with = "de::optional_string")]
+    pub client_oid: Option<String>,
+    pub symbol: String,
+    pub side: Side,
+    #[serde(rename = "type")]
+    pub order_type: OrderType,
+    /// Limit price, `None` for market orders.
+    #[serde(default, deserialize_with = "de::limit_price")]
+    pub price: Option<f64>,
+    /// Size of the order, in contracts.
+    #[serde(deserialize_with = "de::size")]
+    pub size: i64,
+    #[serde(deserialize_with = "de::size")]
+    pub filled_size: i64,
+    #[serde(deserialize_with = "de::float")]
+    pub leverage: f64,
+    #[serde(default)]
+    pub reduce_only: bool,
+    /// Whether the order is still open.
+    #[serde(default)]
+    pub is_active: bool,
+    /// When the order was placed, in milliseconds since the epoch.
+    #[serde(deserialize_with = "de::integer")]
+    pub created_at: u64,
+}
+
+/// Whether a fill added liquidity to the book or took it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+/// A trade of one of the account's orders, as listed by KuCoin's fills API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fill {
+    pub trade_id: String,
+    pub order_id: String,
+    pub symbol: String,
+    pub side: Side,
+    pub liquidity: Liquidity,
+    #[serde(deserialize_with = "de::float")]
+    pub price: f64,
+    /// Size of the trade, in contracts.
+    #[serde(deserialize_with = "de::size")]
+    pub size: i64,
+    #[serde(deserialize_with = "de::float")]
+    pub fee: f64,
+    pub fee_currency: String,
+    /// When the trade happened, in nanoseconds since the epoch.
+    #[serde(rename = "tradeTime", deserialize_with = "de::integer")]
+    pub ts: u64,
+}
+
+/// Which fills `RestClient::get_fills` lists, all of the account's recent
+/// fills unless narrowed down.
+///
+/// # Usage
+/// ```ignore
+/// let query = FillQuery::new().symbol("XBTUSDTM").start_at(from_ms);
+/// let fills = rest.get_fills(&query, 1)?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FillQuery {
+    symbol: Option<String>,
+    order_id: Option<String>,
+    side: Option<Side>,
+    start_at: Option<u64>,
+    end_at: Option<u64>,
+}
+
+impl FillQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only fills of `symbol`.
+    pub fn symbol(mut self, symbol: &str) -> Self {
+        self.symbol = Some(symbol.to_owned());
+        self
+    }
+
+    /// Only fills of the order `order_id`.
+    pub fn order_id(mut self, order_id: &str) -> Self {
+        self.order_id = Some(order_id.to_owned());
+        self
+    }
+
+    /// Only fills on `side`.
+    pub fn side(mut self, side: Side) -> Self {
+        self.side = Some(side);
+        self
+    }
+
+    /// Only fills from `start_at`, in milliseconds since the epoch.
+    pub fn start_at(mut self, start_at: u64) -> Self {
+        self.start_at = Some(start_at);
+        self
+    }
+
+    /// Only fills until `end_at`, in milliseconds since the epoch.
+    pub fn end_at(mut self, end_at: u64) -> Self {
+        self.end_at = Some(end_at);
+        self
+    }
+
+    // Query parameters of the fills API
+    pub(super) fn params(&self) -> Vec<(&'static str, String)> {
+        let side = self.side.map(|side| match side {
+            Side::Buy => "buy".to_owned(),
+            Side::Sell => "sell".to_owned(),
+        });
+        [
+            ("symbol", self.symbol.clone()),
+            ("orderId", self.order_id.clone()),
+            ("side", side),
+            ("startAt", self.start_at.map(|ms| ms.to_string())),
+            ("endAt", self.end_at.map(|ms| ms.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+}
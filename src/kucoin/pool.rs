@@ -0,0 +1,120 @@
+use super::api::Credentials;
+use super::builder::{checked_ping_jitter, Options};
+use super::client::Session;
+use super::error::KucoinError;
+use super::rate_limit::checked_send_rate;
+use super::subscription::Subscription;
+use std::time::Duration;
+
+/// KuCoin allows at most this many topics on a single connection.
+pub const MAX_TOPICS_PER_SESSION: usize = 300;
+
+// Fetches the credentials of a new connection
+type CredentialsFactory = Box<dyn Fn() -> Result<Credentials, KucoinError> + Send + Sync>;
+
+/// Spreads subscriptions across as many `Session`s as needed to stay under
+/// the per-connection topic limit.
+///
+/// Every underlying connection is opened with a freshly fetched token.
+///
+/// # Usage
+/// ```ignore
+/// let mut pool = SessionPool::with_credentials(|| {
+///     Credentials::new_private("key", "secret", "passphrase")
+/// });
+/// ```
+pub struct SessionPool {
+    sessions: Vec<Session>,
+    max_topics_per_session: usize,
+    options: Options,
+    credentials: CredentialsFactory,
+}
+
+impl SessionPool {
+    /// Constructs an empty pool using KuCoin's per-connection topic limit,
+    /// connecting with public tokens.
+    pub fn new() -> Self {
+        Self::with_credentials(Credentials::new_with_token)
+    }
+
+    /// Constructs an empty pool opening a new connection every
+    /// `max_topics_per_session` topics, connecting with public tokens.
+    pub fn with_max_topics(max_topics_per_session: usize) -> Self {
+        Self::new().max_topics(max_topics_per_session)
+    }
+
+    /// Constructs an empty pool using KuCoin's per-connection topic limit,
+    /// connecting each new session with the credentials `credentials`
+    /// returns, e.g. to use a private token or another domain. It is called
+    /// once per connection, since a token cannot be shared.
+    pub fn with_credentials(
+        credentials: impl Fn() -> Result<Credentials, KucoinError> + Send + Sync + 'static,
+    ) -> Self {
+        SessionPool {
+            sessions: Vec::new(),
+            max_topics_per_session: MAX_TOPICS_PER_SESSION,
+            options: Options {
+                max_subscriptions: MAX_TOPICS_PER_SESSION,
+                ..Options::default()
+            },
+            credentials: Box::new(credentials),
+        }
+    }
+
+    /// Opens a new connection every `max_topics_per_session` topics rather
+    /// than at KuCoin's limit.
+    pub fn max_topics(mut self, max_topics_per_session: usize) -> Self {
+        assert!(
+            max_topics_per_session > 0,
+            "A session must hold at least one topic"
+        );
+
+        self.max_topics_per_session = max_topics_per_session;
+        self.options.max_subscriptions = max_topics_per_session;
+        self
+    }
+
+    /// Randomizes the ping cadence of each connection by up to `fraction` of
+    /// the ping interval. See `SessionBuilder::ping_jitter`.
+    pub fn ping_jitter(mut self, fraction: f64) -> Self {
+        self.options.ping_jitter = checked_ping_jitter(fraction);
+        self
+    }
+
+    /// Caps the frames each connection sends. See `SessionBuilder::send_rate`.
+    pub fn send_rate(mut self, messages: u32, per: Duration) -> Self {
+        self.options.send_rate = Some(checked_send_rate(messages, per));
+        self
+    }
+
+    /// Subscribes to the level 2 depth 5 topic of `symbol` on the first
+    /// session with room left, connecting a new one if all are full.
+    pub fn subscribe(&mut self, symbol: &str) -> Result<Subscription, KucoinError> {
+        let session = match self
+            .sessions
+            .iter()
+            .position(|session| session.subscription_count() < self.max_topics_per_session)
+        {
+            Some(i) => &self.sessions[i],
+            None => {
+                let credentials = (self.credentials)()?;
+                let (session, _) = Session::connect_with(&credentials, self.options.clone())?;
+                self.sessions.push(session);
+                self.sessions.last().unwrap()
+            }
+        };
+
+        session.subscribe_level2(symbol)
+    }
+
+    /// Number of connections currently open.
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+}
+
+impl Default for SessionPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::{Duration, Instant};
+
+/// Token bucket spacing out the frames a session sends, so that bursts of
+/// subscriptions stay under the server's inbound message limit. Also spaces
+/// out REST requests, each taking as many tokens as KuCoin weighs it.
+///
+/// Frames may be sent back to back while the bucket holds tokens; once empty,
+/// each frame waits until a token has been refilled.
+#[derive(Debug)]
+pub(super) struct TokenBucket {
+    capacity: f64,
+    // Tokens refilled per second
+    rate: f64,
+    // Goes negative when callers have reserved tokens not yet refilled
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket allowing `messages` frames every `per`.
+    pub fn new(messages: u32, per: Duration, now: Instant) -> Self {
+        TokenBucket {
+            capacity: messages as f64,
+            rate: messages as f64 / per.as_secs_f64(),
+            tokens: messages as f64,
+            updated_at: now,
+        }
+    }
+
+    /// Takes a token, returning how long the caller must wait before sending.
+    ///
+    /// Tokens are reserved in call order, so concurrent senders are spaced
+    /// out rather than woken together.
+    pub fn reserve(&mut self, now: Instant) -> Duration {
+        self.reserve_weight(1, now)
+    }
+
+    /// Same as `reserve`, taking `weight` tokens at once.
+    pub fn reserve_weight(&mut self, weight: u32, now: Instant) -> Duration {
+        self.refill(now);
+
+        self.tokens -= weight as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    /// Takes `weight` tokens only if the bucket holds them, otherwise
+    /// returns how long until it will.
+    pub fn try_take(&mut self, weight: u32, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+
+        let missing = weight as f64 - self.tokens;
+        if missing <= 0.0 {
+            self.tokens -= weight as f64;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(missing / self.rate))
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+        self.updated_at = now;
+    }
+}
+
+pub(super) fn checked_send_rate(messages: u32, per: Duration) -> (u32, Duration) {
+    assert!(
+        messages > 0 && !per.is_zero(),
+        "Send rate must allow at least one message over a non-zero period"
+    );
+    (messages, per)
+}
@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// Number of reconnect events kept by a session.
+pub const RECONNECT_HISTORY_LEN: usize = 32;
+
+/// One attempt at establishing a new connection for a session.
+#[derive(Debug, Clone)]
+pub struct ReconnectEvent {
+    /// Server the attempt connected to.
+    pub endpoint: String,
+    /// 1-based attempt number within the current reconnect.
+    pub attempt: u32,
+    /// How long the session waited before this attempt.
+    pub delay: Duration,
+    pub time: SystemTime,
+    /// Why the attempt failed, if it did.
+    pub result: Result<(), String>,
+}
+
+/// Bounded log of the most recent reconnect events.
+#[derive(Debug, Default)]
+pub(super) struct ReconnectHistory {
+    events: VecDeque<ReconnectEvent>,
+}
+
+impl ReconnectHistory {
+    /// Logs `event` and keeps it, dropping the oldest one when full.
+    pub fn record(&mut self, event: ReconnectEvent) {
+        match &event.result {
+            Ok(()) => log::info!(
+                "Reconnected to {} (attempt {}, delay {:?})",
+                event.endpoint,
+                event.attempt,
+                event.delay
+            ),
+            Err(e) => log::warn!(
+                "Reconnect to {} failed (attempt {}, delay {:?}): {e}",
+                event.endpoint,
+                event.attempt,
+                event.delay
+            ),
+        }
+
+        if self.events.len() == RECONNECT_HISTORY_LEN {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn events(&self) -> Vec<ReconnectEvent> {
+        self.events.iter().cloned().collect()
+    }
+}
+
+/// How a session waits between attempts at reconnecting after its
+/// connection drops.
+///
+/// The delay starts at `initial` and is multiplied by `multiplier` after
+/// every failed attempt, up to `max`. Each delay is then shortened by a
+/// random fraction of itself, up to `jitter`, so that many sessions dropped
+/// together don't reconnect in sync.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+    /// Gives up after this many failed attempts in a row; retries forever
+    /// if `None`.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before the 1-based `attempt`. A negative or NaN `multiplier`
+    /// gives a delay of zero or `max` instead of an invalid one, and `jitter`
+    /// is kept between 0 and 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial.as_secs_f64() * self.multiplier.powi(exponent);
+        let max = self.max.as_secs_f64();
+        let delay = if delay.is_nan() {
+            max
+        } else {
+            delay.clamp(0.0, max)
+        };
+        let jitter = if self.jitter.is_nan() {
+            0.0
+        } else {
+            self.jitter.clamp(0.0, 1.0)
+        };
+
+        Duration::from_secs_f64(delay).mul_f64(1.0 - rand::random_range(0.0..=jitter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_stays_within_zero_and_max() {
+        let backoff = |multiplier, jitter| Backoff {
+            multiplier,
+            jitter,
+            ..Backoff::default()
+        };
+
+        assert_eq!(backoff(-2.0, 0.0).delay(2), Duration::ZERO);
+        assert_eq!(backoff(-2.0, 0.0).delay(3), Duration::from_secs(4));
+        assert_eq!(backoff(f64::NAN, 0.0).delay(2), Duration::from_secs(60));
+        assert_eq!(
+            backoff(f64::INFINITY, 0.0).delay(2),
+            Duration::from_secs(60)
+        );
+        assert_eq!(backoff(2.0, f64::NAN).delay(3), Duration::from_secs(4));
+        assert!(backoff(2.0, 5.0).delay(3) <= Duration::from_secs(4));
+    }
+}
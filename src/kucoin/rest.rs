@@ -0,0 +1,604 @@
+use super::account::{AccountOverview, Position};
+use super::api::FUTURES_API_DOMAIN;
+use super::book::Side;
+use super::contract::ContractInfo;
+use super::error::{KucoinError, RecvError};
+use super::kline::{Candle, KlineInterval};
+use super::orders::{
+    CanceledClientOrder, CanceledOrders, Fill, FillQuery, Order, OrderRequest, PlacedOrder,
+};
+use super::rate_limit::{checked_send_rate, TokenBucket};
+use super::signing::ApiKey;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const ACTIVE_CONTRACTS_ENDPOINT: &str = "/api/v1/contracts/active";
+const CONTRACT_ENDPOINT: &str = "/api/v1/contracts";
+const KLINE_ENDPOINT: &str = "/api/v1/kline/query";
+const ORDERS_ENDPOINT: &str = "/api/v1/orders";
+const CLIENT_ORDERS_ENDPOINT: &str = "/api/v1/orders/client-order";
+const FILLS_ENDPOINT: &str = "/api/v1/fills";
+const ACCOUNT_OVERVIEW_ENDPOINT: &str = "/api/v1/account-overview";
+const POSITIONS_ENDPOINT: &str = "/api/v1/positions";
+const SERVER_TIME_ENDPOINT: &str = "/api/v1/timestamp";
+// Weight each request counts for in its rate limit pool
+const CONTRACTS_WEIGHT: u32 = 3;
+const KLINE_WEIGHT: u32 = 3;
+const PLACE_ORDER_WEIGHT: u32 = 2;
+const CANCEL_ORDER_WEIGHT: u32 = 1;
+const CANCEL_ALL_WEIGHT: u32 = 10;
+const ORDER_LIST_WEIGHT: u32 = 2;
+const ORDER_WEIGHT: u32 = 5;
+const FILLS_WEIGHT: u32 = 5;
+const ACCOUNT_OVERVIEW_WEIGHT: u32 = 5;
+const POSITIONS_WEIGHT: u32 = 2;
+const SERVER_TIME_WEIGHT: u32 = 2;
+// Weight KuCoin allows each pool over `RATE_LIMIT_PERIOD`, at the lowest VIP
+// level
+const PUBLIC_RATE_LIMIT: u32 = 2000;
+const PRIVATE_RATE_LIMIT: u32 = 2000;
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(30);
+// Furthest KuCoin accepts signing timestamps off its own clock
+const MAX_SIGNING_SKEW: Duration = Duration::from_secs(5);
+// Header with the milliseconds until KuCoin resets a rate limit pool
+const RATE_LIMIT_RESET_HEADER: &str = "gw-ratelimit-reset";
+// Items requested per page of paged endpoints
+const PAGE_SIZE: u32 = 100;
+// Most candles KuCoin returns for a single kline request
+const MAX_CANDLES_PER_REQUEST: u64 = 500;
+// KuCoin's code for a successful request
+const SUCCESS_CODE: &str = "200000";
+
+/// Blocking client of KuCoin's futures REST API.
+///
+/// Market data is public; trading needs an API key, see `with_api_key`.
+///
+/// Requests are counted against KuCoin's rate limits, one pool for public
+/// and one for private endpoints, and wait for the pool to refill once it
+/// runs out. Clones share their pools.
+///
+/// Once `measure_clock_skew` has been called, private requests are signed
+/// as of KuCoin's clock rather than the local one.
+///
+/// # Usage
+/// ```ignore
+/// let rest = RestClient::new()?;
+/// let contract = rest.get_contract("XBTUSDTM")?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RestClient {
+    http: reqwest::blocking::Client,
+    domain: String,
+    api_key: Option<ApiKey>,
+    public_limit: Arc<Mutex<TokenBucket>>,
+    private_limit: Arc<Mutex<TokenBucket>>,
+    reject_rate_limited: bool,
+    // KuCoin's clock minus the local one in milliseconds, once measured
+    clock_skew: Arc<Mutex<Option<i64>>>,
+}
+
+/// One page of a paged REST endpoint, numbered from 1.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    pub current_page: u32,
+    pub page_size: u32,
+    /// Number of items over all pages.
+    pub total_num: u32,
+    pub total_page: u32,
+    pub items: Vec<T>,
+}
+
+/// Iterator over every item of a paged REST endpoint, fetching the next page
+/// once the items of the previous one are used up.
+///
+/// Stops after the last page, or after yielding the error a page failed
+/// with.
+///
+/// # Usage
+/// ```ignore
+/// for fill in rest.fills("XBTUSDTM") {
+///     println!("{:?}", fill?);
+/// }
+/// ```
+pub struct Paginated<'a, T> {
+    fetch: Box<dyn FnMut(u32) -> Result<Page<T>, KucoinError> + 'a>,
+    // `None` once the last page was fetched
+    next_page: Option<u32>,
+    items: std::vec::IntoIter<T>,
+}
+
+impl<'a, T> Paginated<'a, T> {
+    // Walks the pages `fetch` returns, starting from the first
+    fn new(fetch: impl FnMut(u32) -> Result<Page<T>, KucoinError> + 'a) -> Self {
+        Paginated {
+            fetch: Box::new(fetch),
+            next_page: Some(1),
+            items: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<T> Iterator for Paginated<'_, T> {
+    type Item = Result<T, KucoinError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.next() {
+                return Some(Ok(item));
+            }
+
+            let page = self.next_page?;
+            match (self.fetch)(page) {
+                Ok(fetched) => {
+                    self.next_page = (fetched.current_page < fetched.total_page
+                        && !fetched.items.is_empty())
+                    .then_some(page + 1);
+                    self.items = fetched.items.into_iter();
+                }
+                Err(e) => {
+                    self.next_page = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+// Every response of the API, carrying either data or the reason it failed
+#[derive(Deserialize)]
+struct Response<T> {
+    code: String,
+    #[serde(default)]
+    msg: Option<String>,
+    data: Option<T>,
+}
+
+impl RestClient {
+    /// Constructs a client of KuCoin's futures API.
+    pub fn new() -> Result<Self, KucoinError> {
+        Self::with_domain(FUTURES_API_DOMAIN)
+    }
+
+    /// Same as `new`, sending requests to `domain` instead, e.g. a sandbox
+    /// or a local test server.
+    pub fn with_domain(domain: &str) -> Result<Self, KucoinError> {
+        let http = reqwest::blocking::Client::builder()
+            .use_native_tls()
+            .build()
+            .map_err(KucoinError::TlsUnavailable)?;
+
+        Ok(RestClient {
+            http,
+            domain: domain.trim_end_matches('/').to_owned(),
+            api_key: None,
+            public_limit: rate_limit(PUBLIC_RATE_LIMIT, RATE_LIMIT_PERIOD),
+            private_limit: rate_limit(PRIVATE_RATE_LIMIT, RATE_LIMIT_PERIOD),
+            reject_rate_limited: false,
+            clock_skew: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Signs every request with the API `key`, `secret` and `passphrase`,
+    /// giving access to private endpoints such as placing orders.
+    pub fn with_api_key(mut self, key: &str, secret: &str, passphrase: &str) -> Self {
+        self.api_key = Some(ApiKey::new(key, secret, passphrase));
+        self
+    }
+
+    /// Caps the weight of requests to public endpoints to `weight` every
+    /// `per`, KuCoin's limit for the lowest VIP level unless set.
+    ///
+    /// # Panics
+    /// If `weight` or `per` is zero.
+    pub fn public_rate_limit(mut self, weight: u32, per: Duration) -> Self {
+        let (weight, per) = checked_send_rate(weight, per);
+        self.public_limit = rate_limit(weight, per);
+        self
+    }
+
+    /// Same as `public_rate_limit`, for private endpoints, e.g. at a higher
+    /// VIP level.
+    ///
+    /// # Panics
+    /// If `weight` or `per` is zero.
+    pub fn private_rate_limit(mut self, weight: u32, per: Duration) -> Self {
+        let (weight, per) = checked_send_rate(weight, per);
+        self.private_limit = rate_limit(weight, per);
+        self
+    }
+
+    /// Fails requests exceeding the rate limit with
+    /// `KucoinError::RateLimited` instead of waiting for the limit to allow
+    /// them.
+    pub fn reject_rate_limited(mut self, reject: bool) -> Self {
+        self.reject_rate_limited = reject;
+        self
+    }
+
+    /// Fetches the time on KuCoin's clock, in milliseconds since the epoch.
+    pub fn get_server_time(&self) -> Result<u64, KucoinError> {
+        self.get(SERVER_TIME_ENDPOINT, SERVER_TIME_WEIGHT)
+    }
+
+    /// Measures how far KuCoin's clock is ahead of the local one, in
+    /// milliseconds, negative if it is behind. Half the round trip is
+    /// assumed to have passed when KuCoin read its clock.
+    ///
+    /// Private requests are signed with the corrected time from then on,
+    /// since KuCoin rejects signatures more than 5 seconds off its clock.
+    pub fn measure_clock_skew(&self) -> Result<i64, KucoinError> {
+        let sent_at = epoch_millis(SystemTime::now());
+        let server_time = self.get_server_time()? as i64;
+        let received_at = epoch_millis(SystemTime::now());
+
+        let clock_skew = server_time - (sent_at + received_at) / 2;
+        if clock_skew.unsigned_abs() > MAX_SIGNING_SKEW.as_millis() as u64 {
+            log::warn!(
+                "Local clock is {clock_skew}ms off KuCoin's, signing as of KuCoin's clock instead"
+            );
+        }
+        *self
+            .clock_skew
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(clock_skew);
+        Ok(clock_skew)
+    }
+
+    /// Fetches the trading rules of every contract currently open for
+    /// trading.
+    pub fn get_active_contracts(&self) -> Result<Vec<ContractInfo>, KucoinError> {
+        self.get(ACTIVE_CONTRACTS_ENDPOINT, CONTRACTS_WEIGHT)
+    }
+
+    /// Fetches the trading rules of the contract `symbol`, e.g. to round
+    /// prices to its tick size.
+    pub fn get_contract(&self, symbol: &str) -> Result<ContractInfo, KucoinError> {
+        self.get(&format!("{CONTRACT_ENDPOINT}/{symbol}"), CONTRACTS_WEIGHT)
+    }
+
+    /// Fetches the candles of `symbol` starting from `from` up to `to`, both
+    /// in milliseconds since the epoch, oldest first, e.g. to backfill
+    /// history before subscribing with `Session::subscribe_klines`.
+    ///
+    /// Spans longer than KuCoin returns at once are fetched in as many
+    /// requests as needed.
+    pub fn get_klines(
+        &self,
+        symbol: &str,
+        interval: KlineInterval,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<Candle>, KucoinError> {
+        let step = interval.duration().as_millis() as u64 * MAX_CANDLES_PER_REQUEST;
+        let mut candles: Vec<Candle> = Vec::new();
+
+        let mut start = from;
+        while start < to {
+            let end = to.min(start + step);
+            let page: Vec<Vec<f64>> = self.get_with(
+                KLINE_ENDPOINT,
+                &[
+                    ("symbol", symbol.to_owned()),
+                    ("granularity", interval.minutes().to_string()),
+                    ("from", start.to_string()),
+                    ("to", end.to_string()),
+                ],
+                KLINE_WEIGHT,
+            )?;
+            for candle in page {
+                let candle = Candle::from_rest(&candle)?;
+                // Pages share the candle on their boundary
+                if candles.last().is_none_or(|last| candle.ts > last.ts) {
+                    candles.push(candle);
+                }
+            }
+            start = end;
+        }
+
+        Ok(candles)
+    }
+
+    /// Places `order`. Needs an API key allowed to trade.
+    pub fn place_order(&self, order: &OrderRequest) -> Result<PlacedOrder, KucoinError> {
+        self.send_signed(
+            Method::POST,
+            ORDERS_ENDPOINT,
+            &[],
+            Some(order),
+            PLACE_ORDER_WEIGHT,
+        )
+    }
+
+    /// Places a good till canceled limit order of `size` contracts of
+    /// `symbol` at `price`. See `place_order` for other settings.
+    pub fn place_limit_order(
+        &self,
+        symbol: &str,
+        side: Side,
+        price: f64,
+        size: i64,
+    ) -> Result<PlacedOrder, KucoinError> {
+        self.place_order(&OrderRequest::limit(symbol, side, price, size))
+    }
+
+    /// Places a market order of `size` contracts of `symbol`. See
+    /// `place_order` for other settings.
+    pub fn place_market_order(
+        &self,
+        symbol: &str,
+        side: Side,
+        size: i64,
+    ) -> Result<PlacedOrder, KucoinError> {
+        self.place_order(&OrderRequest::market(symbol, side, size))
+    }
+
+    /// Cancels the order `order_id`.
+    pub fn cancel_order(&self, order_id: &str) -> Result<CanceledOrders, KucoinError> {
+        self.delete(
+            &format!("{ORDERS_ENDPOINT}/{order_id}"),
+            &[],
+            CANCEL_ORDER_WEIGHT,
+        )
+    }
+
+    /// Cancels the order of `symbol` placed with `client_oid`, see
+    /// `OrderRequest::client_oid`.
+    pub fn cancel_by_client_oid(
+        &self,
+        symbol: &str,
+        client_oid: &str,
+    ) -> Result<CanceledClientOrder, KucoinError> {
+        self.delete(
+            &format!("{CLIENT_ORDERS_ENDPOINT}/{client_oid}"),
+            &[("symbol", symbol.to_owned())],
+            CANCEL_ORDER_WEIGHT,
+        )
+    }
+
+    /// Cancels every open limit order of `symbol`.
+    pub fn cancel_all(&self, symbol: &str) -> Result<CanceledOrders, KucoinError> {
+        self.delete(
+            ORDERS_ENDPOINT,
+            &[("symbol", symbol.to_owned())],
+            CANCEL_ALL_WEIGHT,
+        )
+    }
+
+    /// Fetches page `page` of the open orders of `symbol`, e.g. to reconcile
+    /// `OrderUpdate`s missed while reconnecting.
+    pub fn get_open_orders(&self, symbol: &str, page: u32) -> Result<Page<Order>, KucoinError> {
+        self.get_signed(
+            ORDERS_ENDPOINT,
+            &[
+                ("status", "active".to_owned()),
+                ("symbol", symbol.to_owned()),
+                ("currentPage", page.to_string()),
+                ("pageSize", PAGE_SIZE.to_string()),
+            ],
+            ORDER_LIST_WEIGHT,
+        )
+    }
+
+    /// Iterates over every open order of `symbol`, see `get_open_orders`.
+    pub fn open_orders<'a>(&'a self, symbol: &'a str) -> Paginated<'a, Order> {
+        Paginated::new(move |page| self.get_open_orders(symbol, page))
+    }
+
+    /// Fetches the order `order_id`, open or done.
+    pub fn get_order(&self, order_id: &str) -> Result<Order, KucoinError> {
+        self.get_signed(&format!("{ORDERS_ENDPOINT}/{order_id}"), &[], ORDER_WEIGHT)
+    }
+
+    /// Fetches page `page` of the account's fills matching `query`, newest
+    /// first.
+    pub fn get_fills(&self, query: &FillQuery, page: u32) -> Result<Page<Fill>, KucoinError> {
+        let mut params = query.params();
+        params.push(("currentPage", page.to_string()));
+        params.push(("pageSize", PAGE_SIZE.to_string()));
+
+        self.get_signed(FILLS_ENDPOINT, &params, FILLS_WEIGHT)
+    }
+
+    /// Iterates over every recent fill of `symbol`, newest first.
+    pub fn fills(&self, symbol: &str) -> Paginated<'_, Fill> {
+        self.fills_matching(FillQuery::new().symbol(symbol))
+    }
+
+    /// Iterates over every fill matching `query`, newest first.
+    pub fn fills_matching(&self, query: FillQuery) -> Paginated<'_, Fill> {
+        Paginated::new(move |page| self.get_fills(&query, page))
+    }
+
+    /// Fetches the account's futures balances in `currency`, e.g. `USDT`.
+    pub fn get_account_overview(&self, currency: &str) -> Result<AccountOverview, KucoinError> {
+        self.get_signed(
+            ACCOUNT_OVERVIEW_ENDPOINT,
+            &[("currency", currency.to_owned())],
+            ACCOUNT_OVERVIEW_WEIGHT,
+        )
+    }
+
+    /// Fetches every position of the account.
+    pub fn get_positions(&self) -> Result<Vec<Position>, KucoinError> {
+        self.get_signed(POSITIONS_ENDPOINT, &[], POSITIONS_WEIGHT)
+    }
+
+    // Sends a GET request to `endpoint` and returns the data of the response
+    fn get<T: DeserializeOwned>(&self, endpoint: &str, weight: u32) -> Result<T, KucoinError> {
+        self.get_with(endpoint, &[], weight)
+    }
+
+    // Same as `get`, with query parameters
+    fn get_with<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        query: &[(&str, String)],
+        weight: u32,
+    ) -> Result<T, KucoinError> {
+        self.send(Method::GET, endpoint, query, None::<&()>, false, weight)
+    }
+
+    // Sends a signed GET request to `endpoint`
+    fn get_signed<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        query: &[(&str, String)],
+        weight: u32,
+    ) -> Result<T, KucoinError> {
+        self.send_signed(Method::GET, endpoint, query, None::<&()>, weight)
+    }
+
+    // Sends a signed DELETE request to `endpoint`
+    fn delete<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        query: &[(&str, String)],
+        weight: u32,
+    ) -> Result<T, KucoinError> {
+        self.send_signed(Method::DELETE, endpoint, query, None::<&()>, weight)
+    }
+
+    // Same as `send`, for private endpoints
+    fn send_signed<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        query: &[(&str, String)],
+        body: Option<&impl serde::Serialize>,
+        weight: u32,
+    ) -> Result<T, KucoinError> {
+        self.send(method, endpoint, query, body, true, weight)
+    }
+
+    // Sends a request to `endpoint` and returns the data of the response,
+    // signing it if `signed`. `weight` is counted against the rate limit of
+    // public or private endpoints, depending on `signed`.
+    fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        query: &[(&str, String)],
+        body: Option<&impl serde::Serialize>,
+        signed: bool,
+        weight: u32,
+    ) -> Result<T, KucoinError> {
+        let mut url = reqwest::Url::parse(&format!("{}{endpoint}", self.domain))
+            .map_err(|e| KucoinError::Protocol(format!("Invalid URL for {endpoint}: {e}")))?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        let body = match body {
+            Some(body) => serde_json::to_string(body).map_err(RecvError::from)?,
+            None => String::new(),
+        };
+
+        let api_key = match (&self.api_key, signed) {
+            (_, false) => None,
+            (Some(api_key), true) => Some(api_key),
+            (None, true) => {
+                return Err(KucoinError::NoApiKey {
+                    endpoint: endpoint.to_owned(),
+                })
+            }
+        };
+        // Before signing, as KuCoin refuses signatures more than 5 seconds
+        // old and the wait may be longer
+        self.throttle(signed, weight)?;
+
+        let mut request = self.http.request(method.clone(), url.clone());
+        if let Some(api_key) = api_key {
+            // The signature covers the path and query exactly as sent
+            let path = match url.query() {
+                Some(query) => format!("{}?{query}", url.path()),
+                None => url.path().to_owned(),
+            };
+            let clock_skew = *self
+                .clock_skew
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let timestamp = epoch_millis(SystemTime::now()) + clock_skew.unwrap_or(0);
+            for (name, value) in api_key.headers_at(method.as_str(), &path, &body, timestamp as u64)
+            {
+                request = request.header(name, value);
+            }
+        }
+        if !body.is_empty() {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+        }
+
+        let response = request.send()?;
+        let status = response.status();
+
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let reset = response
+                .headers()
+                .get(RATE_LIMIT_RESET_HEADER)
+                .and_then(|reset| reset.to_str().ok()?.parse::<u64>().ok());
+            return Err(KucoinError::RateLimited {
+                retry_after: Duration::from_millis(reset.unwrap_or(0)),
+            });
+        }
+
+        // Failures come with a code and message in the body, whatever the status
+        let body: Response<T> = match response.json() {
+            Ok(body) => body,
+            Err(e) if status.is_success() => return Err(e.into()),
+            Err(_) => {
+                return Err(KucoinError::Protocol(format!(
+                    "{endpoint} answered {status}"
+                )))
+            }
+        };
+        match body {
+            Response {
+                code,
+                data: Some(data),
+                ..
+            } if code == SUCCESS_CODE => Ok(data),
+            Response { code, msg, .. } => Err(KucoinError::Api {
+                code,
+                msg: msg.unwrap_or_default(),
+            }),
+        }
+    }
+
+    // Counts `weight` against the rate limit of public or private endpoints,
+    // waiting until it allows the request or failing if it would have to
+    fn throttle(&self, private: bool, weight: u32) -> Result<(), KucoinError> {
+        let limit = if private {
+            &self.private_limit
+        } else {
+            &self.public_limit
+        };
+        let mut limit = limit.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if self.reject_rate_limited {
+            return limit
+                .try_take(weight, Instant::now())
+                .map_err(|retry_after| KucoinError::RateLimited { retry_after });
+        }
+        let wait = limit.reserve_weight(weight, Instant::now());
+        drop(limit);
+        if !wait.is_zero() {
+            log::debug!("REST rate limit reached, delaying request by {:?}", wait);
+            std::thread::sleep(wait);
+        }
+        Ok(())
+    }
+}
+
+fn epoch_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn rate_limit(weight: u32, per: Duration) -> Arc<Mutex<TokenBucket>> {
+    Arc::new(Mutex::new(TokenBucket::new(weight, per, Instant::now())))
+}
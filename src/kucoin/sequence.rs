@@ -0,0 +1,73 @@
+use super::book::Price;
+
+/// A sequence drop to at most this value is taken as the exchange restarting
+/// the topic's sequence rather than a gap.
+pub const SEQUENCE_RESET_MAX: u64 = 1_000;
+
+/// Notable changes in the sequence numbers of a topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedEvent {
+    /// Messages are missing, or arrived out of order.
+    Gap {
+        topic: String,
+        expected: u64,
+        received: u64,
+    },
+    /// The exchange restarted the topic's sequence.
+    SequenceReset {
+        topic: String,
+        previous: u64,
+        received: u64,
+    },
+    /// A book of the topic had its best bid at or above its best ask. Only
+    /// reported for subscriptions checking for them, see `CrossedBooks`.
+    CrossedBook {
+        topic: String,
+        best_bid: Price,
+        best_ask: Price,
+    },
+    /// The book of the topic was rebuilt from a snapshot after a gap and is
+    /// up to date again as of `sequence`.
+    Resynced { topic: String, sequence: u64 },
+}
+
+/// Follows the sequence numbers of a single snapshot topic, such as depth 5,
+/// which skips sequences between messages. The incremental feed checks its
+/// own, see `BookSync`.
+#[derive(Debug)]
+pub(super) struct SequenceTracker {
+    topic: String,
+    last: Option<u64>,
+}
+
+impl SequenceTracker {
+    pub fn new(topic: &str) -> Self {
+        SequenceTracker {
+            topic: topic.to_owned(),
+            last: None,
+        }
+    }
+
+    /// Records `sequence`, returning an event if it doesn't follow the last one.
+    pub fn observe(&mut self, sequence: u64) -> Option<FeedEvent> {
+        let previous = self.last.replace(sequence)?;
+
+        if sequence < previous && sequence <= SEQUENCE_RESET_MAX {
+            return Some(FeedEvent::SequenceReset {
+                topic: self.topic.clone(),
+                previous,
+                received: sequence,
+            });
+        }
+
+        if sequence > previous {
+            return None;
+        }
+
+        Some(FeedEvent::Gap {
+            topic: self.topic.clone(),
+            expected: previous + 1,
+            received: sequence,
+        })
+    }
+}
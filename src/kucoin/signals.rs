@@ -0,0 +1,122 @@
+use super::book::{MarketBook, Side};
+use super::subscription::Subscription;
+use std::collections::VecDeque;
+
+/// Indicators derived from a single book, along with the ones rolling over
+/// the books before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Signal {
+    /// `(bid_size - ask_size) / (bid_size + ask_size)` at the top of book,
+    /// from -1 (only asks) to 1 (only bids). 0 if the top of book is empty.
+    pub imbalance: f64,
+    /// Mean `imbalance` over the books of the window, this one included.
+    pub rolling_imbalance: f64,
+    /// See `MarketBook::microprice`.
+    pub microprice: Option<f64>,
+    /// Imbalance of all levels held, each weighted by `1 / (n + 1)` for the
+    /// `n`th best level so that levels near the top weigh more. From -1
+    /// (selling pressure) to 1 (buying pressure).
+    pub pressure: f64,
+}
+
+/// Computes `Signal`s from successive books of one symbol.
+#[derive(Debug, Clone)]
+pub struct SignalTracker {
+    window: usize,
+    imbalances: VecDeque<f64>,
+}
+
+impl SignalTracker {
+    /// Tracks rolling indicators over the last `window` books.
+    ///
+    /// # Panics
+    /// If `window` is zero.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "Signal window must hold at least one book");
+        SignalTracker {
+            window,
+            imbalances: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Takes in the next book and returns its signal.
+    pub fn update(&mut self, book: &MarketBook) -> Signal {
+        let imbalance = imbalance(
+            book.best_bid().map_or(0.0, |level| level.size as f64),
+            book.best_ask().map_or(0.0, |level| level.size as f64),
+        );
+
+        if self.imbalances.len() == self.window {
+            self.imbalances.pop_front();
+        }
+        self.imbalances.push_back(imbalance);
+        let rolling_imbalance = self.imbalances.iter().sum::<f64>() / self.imbalances.len() as f64;
+
+        Signal {
+            imbalance,
+            rolling_imbalance,
+            microprice: book.microprice(),
+            pressure: pressure(book),
+        }
+    }
+}
+
+// Imbalance between two sizes, 0 when both are empty
+fn imbalance(bid_size: f64, ask_size: f64) -> f64 {
+    let total = bid_size + ask_size;
+    if total == 0.0 {
+        return 0.0;
+    }
+    (bid_size - ask_size) / total
+}
+
+fn pressure(book: &MarketBook) -> f64 {
+    let weighted = |side| -> f64 {
+        book.levels(side)
+            .iter()
+            .enumerate()
+            .map(|(n, &(_, size))| size as f64 / (n + 1) as f64)
+            .sum()
+    };
+
+    imbalance(weighted(Side::Buy), weighted(Side::Sell))
+}
+
+/// The books of a subscription turned into signals, see
+/// `Subscription::signals`.
+pub struct Signals {
+    subscription: Subscription,
+    tracker: SignalTracker,
+}
+
+impl Signals {
+    /// The subscription the books come from.
+    pub fn subscription(&self) -> &Subscription {
+        &self.subscription
+    }
+}
+
+impl Iterator for Signals {
+    type Item = Signal;
+
+    /// Blocks until the next book arrives, ending once the subscription is
+    /// closed.
+    fn next(&mut self) -> Option<Signal> {
+        let book = self.subscription.recv().ok()?;
+        Some(self.tracker.update(&book))
+    }
+}
+
+impl Subscription {
+    /// Turns every book received into a `Signal`, rolling over the last
+    /// `window` books, e.g. to prototype signals directly off the feed.
+    ///
+    /// # Panics
+    /// If `window` is zero.
+    pub fn signals(self, window: usize) -> Signals {
+        Signals {
+            subscription: self,
+            tracker: SignalTracker::new(window),
+        }
+    }
+}
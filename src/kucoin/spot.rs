@@ -0,0 +1,138 @@
+use super::book::Side;
+use super::error::RecvError;
+use super::json::{de, payload, payload_from_str};
+use serde::Deserialize;
+
+/// A change to one price level of a spot book, sent as
+/// `[price, size, sequence]`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct SpotLevelChange {
+    #[serde(deserialize_with = "de::float")]
+    pub price: f64,
+    /// New size of the level, 0 once it is empty.
+    #[serde(deserialize_with = "de::float")]
+    pub size: f64,
+    #[serde(deserialize_with = "de::integer")]
+    pub sequence: u64,
+}
+
+/// A batch of changes to the book of a spot symbol, as sent on its level 2
+/// topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotBookChanges {
+    pub asks: Vec<SpotLevelChange>,
+    pub bids: Vec<SpotLevelChange>,
+    pub sequence_start: u64,
+    pub sequence_end: u64,
+    /// When the changes happened, in milliseconds since the epoch.
+    pub time: u64,
+}
+
+impl SpotBookChanges {
+    /// Parses the data message of a spot level 2 topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        Ok(SpotBookChanges::from_raw(payload(msg)?))
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        Ok(SpotBookChanges::from_raw(payload_from_str(text)?))
+    }
+
+    fn from_raw(raw: RawSpotBookChanges) -> Self {
+        SpotBookChanges {
+            asks: raw.changes.asks,
+            bids: raw.changes.bids,
+            sequence_start: raw.sequence_start,
+            sequence_end: raw.sequence_end,
+            time: raw.time,
+        }
+    }
+}
+
+// The data message of a spot level 2 topic
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSpotBookChanges {
+    changes: RawSpotChanges,
+    #[serde(deserialize_with = "de::integer")]
+    sequence_start: u64,
+    #[serde(deserialize_with = "de::integer")]
+    sequence_end: u64,
+    #[serde(deserialize_with = "de::integer")]
+    time: u64,
+}
+
+#[derive(Deserialize)]
+struct RawSpotChanges {
+    asks: Vec<SpotLevelChange>,
+    bids: Vec<SpotLevelChange>,
+}
+
+/// Last trade and best bid and ask of a spot symbol, as sent on its ticker
+/// topic.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpotTicker {
+    /// Price of the last trade.
+    #[serde(deserialize_with = "de::float")]
+    pub price: f64,
+    /// Size of the last trade.
+    #[serde(deserialize_with = "de::float")]
+    pub size: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub best_bid: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub best_bid_size: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub best_ask: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub best_ask_size: f64,
+    #[serde(deserialize_with = "de::integer")]
+    pub sequence: u64,
+    /// When the ticker was updated, in milliseconds since the epoch.
+    #[serde(deserialize_with = "de::integer")]
+    pub time: u64,
+}
+
+impl SpotTicker {
+    /// Parses the data message of a spot ticker topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        payload(msg)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        payload_from_str(text)
+    }
+}
+
+/// A trade of a spot symbol, as sent on its match topic.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpotTrade {
+    #[serde(deserialize_with = "de::float")]
+    pub price: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub size: f64,
+    /// Side of the taker.
+    pub side: Side,
+    pub trade_id: String,
+    #[serde(deserialize_with = "de::integer")]
+    pub sequence: u64,
+    /// When the trade happened, in nanoseconds since the epoch.
+    #[serde(deserialize_with = "de::integer")]
+    pub time: u64,
+}
+
+impl SpotTrade {
+    /// Parses the data message of a spot match topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        payload(msg)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        payload_from_str(text)
+    }
+}
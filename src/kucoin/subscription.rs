@@ -0,0 +1,106 @@
+use super::book::{BookDepth, MarketBook};
+use super::client::Session;
+use super::topic::Topic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+/// How a subscription delivers books to its consumer.
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionOptions {
+    /// Deliver at most one book per interval. Books arriving sooner after
+    /// the last delivered one are dropped, so the consumer always gets the
+    /// freshest book at a capped rate.
+    pub min_interval: Option<Duration>,
+    /// What to do with books whose best bid is at or above the best ask.
+    pub crossed_books: CrossedBooks,
+    /// Levels of each side to subscribe to, 5 unless set.
+    pub depth: BookDepth,
+}
+
+/// Handling of crossed or locked books, see `MarketBook::is_crossed`.
+///
+/// Except with `Deliver`, every such book is also reported as a
+/// `FeedEvent::CrossedBook` to the session's feed events, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrossedBooks {
+    /// Deliver them like any other book.
+    #[default]
+    Deliver,
+    /// Deliver them and log a warning.
+    Log,
+    /// Drop them without delivering.
+    Drop,
+}
+
+/// A live subscription to a topic, receiving its messages as `T`, e.g.
+/// books for level 2 topics or trades for execution topics.
+///
+/// Dropping it unsubscribes from the topic on the server, so consumers
+/// cannot leak server-side subscriptions by simply going away.
+pub struct Subscription<T = MarketBook> {
+    session: Session,
+    topic: Topic,
+    rx: mpsc::Receiver<T>,
+    paused: Arc<AtomicBool>,
+}
+
+impl<T> Subscription<T> {
+    pub(super) fn new(
+        session: Session,
+        topic: Topic,
+        rx: mpsc::Receiver<T>,
+        paused: Arc<AtomicBool>,
+    ) -> Self {
+        Subscription {
+            session,
+            topic,
+            rx,
+            paused,
+        }
+    }
+
+    /// The topic this subscription receives messages from.
+    pub fn topic(&self) -> &Topic {
+        &self.topic
+    }
+
+    /// Stops delivering messages without unsubscribing from the server.
+    /// Messages received while paused are dropped.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Resumes delivering messages after `pause`.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until the next message arrives.
+    pub fn recv(&self) -> Result<T, mpsc::RecvError> {
+        self.rx.recv()
+    }
+
+    /// Blocks until the next message arrives or `timeout` elapses.
+    ///
+    /// Useful for consumers that need to wake up periodically, e.g. to check
+    /// the health of the feed or whether they should shut down.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, mpsc::RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+
+    /// Returns the next message if one is already waiting.
+    pub fn try_recv(&self) -> Result<T, mpsc::TryRecvError> {
+        self.rx.try_recv()
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.session.unsubscribe_topic(&self.topic);
+    }
+}
@@ -0,0 +1,96 @@
+use super::book::Level;
+use super::error::RecvError;
+use super::json::{de, payload, payload_from_str};
+use serde::{Deserialize, Serialize};
+
+/// Best bid and ask of a futures contract, as sent on its tickerV2 topic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawTicker")]
+pub struct Ticker {
+    pub best_bid: Level,
+    pub best_ask: Level,
+    pub sequence: u64,
+    /// When the ticker was updated, in nanoseconds since the epoch.
+    pub ts: u64,
+}
+
+impl Ticker {
+    /// Parses the data message of a tickerV2 topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        payload(msg)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        payload_from_str(text)
+    }
+}
+
+// A tickerV2 message, sending both sides as flat fields
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawTicker {
+    #[serde(deserialize_with = "de::float")]
+    best_bid_price: f64,
+    #[serde(deserialize_with = "de::size")]
+    best_bid_size: i64,
+    #[serde(deserialize_with = "de::float")]
+    best_ask_price: f64,
+    #[serde(deserialize_with = "de::size")]
+    best_ask_size: i64,
+    #[serde(deserialize_with = "de::integer")]
+    sequence: u64,
+    #[serde(deserialize_with = "de::integer")]
+    ts: u64,
+}
+
+impl From<RawTicker> for Ticker {
+    fn from(raw: RawTicker) -> Self {
+        Ticker {
+            best_bid: Level {
+                price: raw.best_bid_price,
+                size: raw.best_bid_size,
+            },
+            best_ask: Level {
+                price: raw.best_ask_price,
+                size: raw.best_ask_size,
+            },
+            sequence: raw.sequence,
+            ts: raw.ts,
+        }
+    }
+}
+
+/// Rolling 24 hour statistics of a futures contract, as sent on its
+/// snapshot topic.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarketSnapshot {
+    /// Volume traded over the last 24 hours, in contracts.
+    #[serde(deserialize_with = "de::float")]
+    pub volume: f64,
+    /// Value traded over the last 24 hours.
+    #[serde(deserialize_with = "de::float")]
+    pub turnover: f64,
+    #[serde(deserialize_with = "de::float")]
+    pub last_price: f64,
+    /// Price change over the last 24 hours, as a fraction, e.g. `0.0043`
+    /// for +0.43%.
+    #[serde(rename = "priceChgPct", deserialize_with = "de::float")]
+    pub price_change_pct: f64,
+    /// When the statistics were computed, in nanoseconds since the epoch.
+    #[serde(deserialize_with = "de::integer")]
+    pub ts: u64,
+}
+
+impl MarketSnapshot {
+    /// Parses the data message of a snapshot topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        payload(msg)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        payload_from_str(text)
+    }
+}
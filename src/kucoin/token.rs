@@ -0,0 +1,23 @@
+use super::api::Credentials;
+use super::error::KucoinError;
+
+/// Source of the credentials a session connects with whenever it replaces
+/// its connection, i.e. when rotating the token or reconnecting.
+///
+/// KuCoin's tokens are short-lived, so a new one is needed for every
+/// connection.
+pub trait TokenProvider: Send + Sync {
+    /// Credentials to connect with next, given the ones currently in use.
+    /// Failures of other sources can be reported as `KucoinError::Token`.
+    fn fetch(&self, current: &Credentials) -> Result<Credentials, KucoinError>;
+}
+
+/// Fetches a fresh public token from KuCoin's API, see `Credentials::refresh`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ApiTokenProvider;
+
+impl TokenProvider for ApiTokenProvider {
+    fn fetch(&self, current: &Credentials) -> Result<Credentials, KucoinError> {
+        current.refresh()
+    }
+}
@@ -0,0 +1,209 @@
+use super::book::BookDepth;
+use super::error::RecvError;
+use super::kline::KlineInterval;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+const CONTRACT_MARKET: &str = "contractMarket";
+const CONTRACT: &str = "contract";
+const CONTRACT_ACCOUNT: &str = "contractAccount";
+const SPOT_MARKET: &str = "market";
+const LEVEL2: &str = "level2";
+const EXECUTION: &str = "execution";
+const TICKER_V2: &str = "tickerV2";
+const LEVEL3_V2: &str = "level3v2";
+const LIMIT_CANDLE: &str = "limitCandle";
+const INSTRUMENT: &str = "instrument";
+const ANNOUNCEMENT: &str = "announcement";
+const SNAPSHOT: &str = "snapshot";
+const TICKER: &str = "ticker";
+const MATCH: &str = "match";
+const TRADE_ORDERS: &str = "tradeOrders";
+const ADVANCED_ORDERS: &str = "advancedOrders";
+const POSITION: &str = "position";
+const WALLET: &str = "wallet";
+
+/// A KuCoin topic, e.g. `/contractMarket/level2Depth5:ETHUSDTM`.
+///
+/// Made of a market (`contractMarket`, or `market` for spot), a channel (`level2`), an optional
+/// depth (`5`) suffixed to the channel, and an optional symbol.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Topic {
+    market: String,
+    channel: String,
+    depth: Option<u32>,
+    symbol: Option<String>,
+}
+
+impl Topic {
+    pub fn new(market: &str, channel: &str, depth: Option<u32>, symbol: Option<&str>) -> Self {
+        Topic {
+            market: market.to_owned(),
+            channel: channel.to_owned(),
+            depth,
+            symbol: symbol.map(str::to_owned),
+        }
+    }
+
+    /// The level 2 depth 5 topic of a futures `symbol`.
+    pub fn level2_depth5(symbol: &str) -> Self {
+        Topic::level2(symbol, BookDepth::Five)
+    }
+
+    /// The level 2 snapshot topic of a futures `symbol` at `depth`.
+    pub fn level2(symbol: &str, depth: BookDepth) -> Self {
+        let depth = depth.levels() as u32;
+        Topic::new(CONTRACT_MARKET, LEVEL2, Some(depth), Some(symbol))
+    }
+
+    /// The incremental level 2 topic of a futures `symbol`, sending every
+    /// change to its book.
+    pub fn level2_changes(symbol: &str) -> Self {
+        Topic::new(CONTRACT_MARKET, LEVEL2, None, Some(symbol))
+    }
+
+    /// The trade execution topic of a futures `symbol`.
+    pub fn execution(symbol: &str) -> Self {
+        Topic::new(CONTRACT_MARKET, EXECUTION, None, Some(symbol))
+    }
+
+    /// The best bid and ask topic of a futures `symbol`.
+    pub fn ticker_v2(symbol: &str) -> Self {
+        Topic::new(CONTRACT_MARKET, TICKER_V2, None, Some(symbol))
+    }
+
+    /// The level 3 topic of a futures `symbol`, following every order.
+    pub fn level3_v2(symbol: &str) -> Self {
+        Topic::new(CONTRACT_MARKET, LEVEL3_V2, None, Some(symbol))
+    }
+
+    /// The kline topic of a futures `symbol`, sending its candles of
+    /// `interval`.
+    pub fn candles(symbol: &str, interval: KlineInterval) -> Self {
+        let symbol = format!("{symbol}_{interval}");
+        Topic::new(CONTRACT_MARKET, LIMIT_CANDLE, None, Some(&symbol))
+    }
+
+    /// The instrument topic of a futures `symbol`, sending its mark price,
+    /// index price and funding rate.
+    pub fn instrument(symbol: &str) -> Self {
+        Topic::new(CONTRACT, INSTRUMENT, None, Some(symbol))
+    }
+
+    /// The announcement topic of all futures contracts, sending funding
+    /// settlements.
+    pub fn announcement() -> Self {
+        Topic::new(CONTRACT, ANNOUNCEMENT, None, None)
+    }
+
+    /// The 24 hour statistics topic of a futures `symbol`.
+    pub fn snapshot(symbol: &str) -> Self {
+        Topic::new(CONTRACT_MARKET, SNAPSHOT, None, Some(symbol))
+    }
+
+    /// The incremental level 2 topic of a spot `symbol`, e.g. `BTC-USDT`.
+    pub fn spot_level2(symbol: &str) -> Self {
+        Topic::new(SPOT_MARKET, LEVEL2, None, Some(symbol))
+    }
+
+    /// The ticker topic of a spot `symbol`.
+    pub fn spot_ticker(symbol: &str) -> Self {
+        Topic::new(SPOT_MARKET, TICKER, None, Some(symbol))
+    }
+
+    /// The trade topic of a spot `symbol`.
+    pub fn spot_match(symbol: &str) -> Self {
+        Topic::new(SPOT_MARKET, MATCH, None, Some(symbol))
+    }
+
+    /// The private topic of the account's own futures orders.
+    pub fn trade_orders() -> Self {
+        Topic::new(CONTRACT_MARKET, TRADE_ORDERS, None, None)
+    }
+
+    /// The private topic of the account's own futures stop orders.
+    pub fn advanced_orders() -> Self {
+        Topic::new(CONTRACT_MARKET, ADVANCED_ORDERS, None, None)
+    }
+
+    /// The private topic of the account's position in a futures `symbol`.
+    pub fn position(symbol: &str) -> Self {
+        Topic::new(CONTRACT, POSITION, None, Some(symbol))
+    }
+
+    /// The private topic of the account's futures wallet.
+    pub fn wallet() -> Self {
+        Topic::new(CONTRACT_ACCOUNT, WALLET, None, None)
+    }
+
+    pub fn market(&self) -> &str {
+        &self.market
+    }
+
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    pub fn depth(&self) -> Option<u32> {
+        self.depth
+    }
+
+    /// Symbol the topic is about, or several comma separated symbols for a
+    /// topic multiplexing them.
+    pub fn symbol(&self) -> Option<&str> {
+        self.symbol.as_deref()
+    }
+
+    /// The same topic for another symbol.
+    pub fn with_symbol(&self, symbol: &str) -> Self {
+        Topic {
+            symbol: Some(symbol.to_owned()),
+            ..self.clone()
+        }
+    }
+}
+
+impl Display for Topic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "/{}/{}", self.market, self.channel)?;
+        if let Some(depth) = self.depth {
+            write!(f, "Depth{depth}")?;
+        }
+        if let Some(symbol) = &self.symbol {
+            write!(f, ":{symbol}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Topic {
+    type Err = RecvError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RecvError::InvalidFormat(format!("{s} is not a topic"));
+
+        let (path, symbol) = match s.split_once(':') {
+            Some((path, symbol)) => (path, Some(symbol)),
+            None => (s, None),
+        };
+        let (market, channel) = path
+            .strip_prefix('/')
+            .and_then(|path| path.split_once('/'))
+            .ok_or_else(invalid)?;
+        if market.is_empty() || channel.is_empty() || symbol == Some("") {
+            return Err(invalid());
+        }
+
+        // Only a trailing `Depth<n>` is a depth
+        let depth = channel
+            .rsplit_once("Depth")
+            .filter(|(name, _)| !name.is_empty())
+            .and_then(|(name, depth)| Some((name, depth.parse::<u32>().ok()?)));
+        let (channel, depth) = match depth {
+            Some((name, depth)) => (name, Some(depth)),
+            None => (channel, None),
+        };
+
+        Ok(Topic::new(market, channel, depth, symbol))
+    }
+}
@@ -0,0 +1,33 @@
+use super::book::Side;
+use super::error::RecvError;
+use super::json::{de, payload, payload_from_str};
+use serde::{Deserialize, Serialize};
+
+/// A trade of a futures contract, as sent on its execution topic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct Trade {
+    #[serde(deserialize_with = "de::float")]
+    pub price: f64,
+    /// Size of the trade, in contracts.
+    #[serde(deserialize_with = "de::size")]
+    pub size: i64,
+    /// Side of the taker.
+    pub side: Side,
+    pub trade_id: String,
+    /// When the trade happened, in nanoseconds since the epoch.
+    #[serde(deserialize_with = "de::integer")]
+    pub ts: u64,
+}
+
+impl Trade {
+    /// Parses the data message of an execution topic.
+    pub fn new(msg: &serde_json::Value) -> Result<Self, RecvError> {
+        payload(msg)
+    }
+
+    /// Same as `new`, parsing the message text directly.
+    pub fn from_json(text: &str) -> Result<Self, RecvError> {
+        payload_from_str(text)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod kucoin;
+pub mod sinks;
+
+pub use kucoin::{Credentials, MarketBook, RecvError, Session};
@@ -1,12 +1,277 @@
-mod kucoin;
+mod config;
+#[cfg(feature = "tui")]
+mod tui;
+
+use clap::Parser;
+use config::{parse_depth, parse_output, CaptureTopic, Config, OutputTarget};
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tgt_warmup::kucoin::{
+    BookDepth, MarketBook, SessionBuilder, Subscription, SubscriptionOptions,
+};
+use tgt_warmup::sinks::{CsvRecorder, DEFAULT_FLUSH_INTERVAL};
+use tgt_warmup::Credentials;
+
+/// Captures market data of KuCoin futures contracts as JSON lines, each
+/// tagged with its symbol and topic, or level 2 books as CSV.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// TOML file to read settings from; flags take precedence over it
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Contract to capture, e.g. XBTUSDTM; repeat to capture several at once
+    /// [default: ETHUSDTM]
+    #[arg(long = "symbol")]
+    symbols: Vec<String>,
+    /// Levels of each side of level 2 books, 5 or 50 [default: 5]
+    #[arg(long, value_parser = parse_depth)]
+    depth: Option<BookDepth>,
+    /// Topic to capture [default: level2]
+    #[arg(long, value_enum)]
+    topic: Option<CaptureTopic>,
+    /// File to write JSON lines to, or csv:FILE to write level 2 books as
+    /// CSV; stdout unless set
+    #[arg(long, value_parser = parse_output)]
+    output: Option<OutputTarget>,
+    /// Seconds to capture for, until interrupted unless set
+    #[arg(long)]
+    duration: Option<u64>,
+    /// Show the books of the symbols live instead of writing them out
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    tui: bool,
+}
+
+// Where every capture thread writes its lines
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+// Where captured messages go
+enum Sink {
+    Json(Output),
+    Csv(Arc<Mutex<CsvRecorder>>),
+}
+
+// A line of output: a message and where it came from
+#[derive(Serialize)]
+struct Tagged<'a, T> {
+    symbol: &'a str,
+    topic: CaptureTopic,
+    data: &'a T,
+}
 
 fn main() {
-    let credentials = kucoin::Credentials::new_with_token().unwrap();
-    let (_session, response, rx) = kucoin::Session::start(&credentials, "ETHUSDTM").unwrap();
+    env_logger::init();
+
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    let symbols = if !args.symbols.is_empty() {
+        args.symbols
+    } else if !config.symbols.is_empty() {
+        config.symbols.clone()
+    } else {
+        vec!["ETHUSDTM".to_owned()]
+    };
+    let topics = match args.topic {
+        Some(topic) => vec![topic],
+        None if config.topics.is_empty() => vec![CaptureTopic::Level2],
+        None => config.topics.clone(),
+    };
+    let depth = args.depth.or(config.depth()).unwrap_or_default();
+    let target = args.output.or(config.output.target());
+    if let Some(OutputTarget::Csv(_)) = target {
+        if let Some(topic) = topics.iter().find(|&&topic| topic != CaptureTopic::Level2) {
+            return Err(format!("csv output only holds level 2 books, not {topic:?}").into());
+        }
+    }
+
+    let deadline = args
+        .duration
+        .or(config.duration)
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+
+    let credentials = match &config.api {
+        Some(api) => Credentials::new_private(&api.key, &api.secret, &api.passphrase)?,
+        None => Credentials::from_env()?,
+    };
+    let (session, response, _) = SessionBuilder::new(credentials)
+        .reconnect(config.reconnect.backoff())
+        .start()?;
+    log::info!("Handshake response: {:?}", response);
 
-    println!("Handshake response: {:?}", response);
+    #[cfg(feature = "tui")]
+    if args.tui {
+        let symbols: Vec<&str> = symbols.iter().map(String::as_str).collect();
+        let options = SubscriptionOptions {
+            depth,
+            ..Default::default()
+        };
+        let subscriptions = session.subscribe_level2_many_with(&symbols, options)?;
+        tui::run(subscriptions.into_iter().collect(), deadline)?;
+        session.close();
+        return Ok(());
+    }
+
+    let sink = match target {
+        Some(OutputTarget::Csv(path)) => {
+            let flush_interval = config
+                .output
+                .flush_ms
+                .map_or(DEFAULT_FLUSH_INTERVAL, Duration::from_millis);
+            let recorder = CsvRecorder::create(path)?.flush_interval(flush_interval);
+            Sink::Csv(Arc::new(Mutex::new(recorder)))
+        }
+        target => {
+            let output: Box<dyn Write + Send> = match target {
+                Some(OutputTarget::Json(path)) => Box::new(LineWriter::new(File::create(path)?)),
+                _ => Box::new(io::stdout()),
+            };
+            Sink::Json(Arc::new(Mutex::new(output)))
+        }
+    };
+
+    let mut captures = Vec::new();
+    for &topic in &topics {
+        match topic {
+            CaptureTopic::Level2 => {
+                let options = SubscriptionOptions {
+                    depth,
+                    ..Default::default()
+                };
+                let symbols: Vec<&str> = symbols.iter().map(String::as_str).collect();
+                for (_, subscription) in session.subscribe_level2_many_with(&symbols, options)? {
+                    captures.push(match &sink {
+                        Sink::Json(output) => {
+                            let output = output.clone();
+                            thread::spawn(move || capture(subscription, topic, output, deadline))
+                        }
+                        Sink::Csv(recorder) => {
+                            let recorder = recorder.clone();
+                            thread::spawn(move || record(subscription, recorder, deadline))
+                        }
+                    });
+                }
+            }
+            CaptureTopic::Ticker => {
+                let Sink::Json(output) = &sink else {
+                    unreachable!("csv output only holds level 2 books");
+                };
+                for symbol in &symbols {
+                    let subscription = session.subscribe_ticker(symbol)?;
+                    let output = output.clone();
+                    captures.push(thread::spawn(move || {
+                        capture(subscription, topic, output, deadline)
+                    }));
+                }
+            }
+            CaptureTopic::Trades => {
+                let Sink::Json(output) = &sink else {
+                    unreachable!("csv output only holds level 2 books");
+                };
+                for symbol in &symbols {
+                    let subscription = session.subscribe_executions(symbol)?;
+                    let output = output.clone();
+                    captures.push(thread::spawn(move || {
+                        capture(subscription, topic, output, deadline)
+                    }));
+                }
+            }
+        }
+    }
+
+    for capture in captures {
+        if let Err(e) = capture.join().expect("Capture thread panicked") {
+            log::error!("Cannot write output: {e}");
+        }
+    }
+    session.close();
+    Ok(())
+}
+
+// Writes every message of `subscription` as a line of JSON, tagged with its
+// symbol and `topic`, until `deadline`, if any, or until the subscription
+// ends
+fn capture<T: Serialize>(
+    subscription: Subscription<T>,
+    topic: CaptureTopic,
+    output: Output,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    let symbol = subscription.topic().symbol().unwrap_or_default().to_owned();
+    while let Some(msg) = next(&subscription, deadline) {
+        let mut output = output.lock().unwrap();
+        let line = Tagged {
+            symbol: &symbol,
+            topic,
+            data: &msg,
+        };
+        serde_json::to_writer(&mut *output, &line)?;
+        writeln!(output)?;
+    }
+
+    output.lock().unwrap().flush()
+}
 
+// Writes every book of `subscription` as a row of CSV until `deadline`, if
+// any, or until the subscription ends
+fn record(
+    subscription: Subscription<MarketBook>,
+    recorder: Arc<Mutex<CsvRecorder>>,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    let symbol = subscription.topic().symbol().unwrap_or_default().to_owned();
     loop {
-        println!("{}", rx.recv().unwrap());
+        // Wakes up to flush rows buffered while no book arrives
+        let flush_due = recorder.lock().unwrap().flush_due();
+        let wake = deadline.into_iter().chain(flush_due).min();
+        let book = match wake {
+            Some(wake) => subscription.recv_timeout(wake.saturating_duration_since(Instant::now())),
+            None => subscription
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match book {
+            Ok(book) => recorder.lock().unwrap().record(&symbol, &book)?,
+            Err(RecvTimeoutError::Timeout) => {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    break;
+                }
+                recorder.lock().unwrap().flush_if_due()?;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    recorder.lock().unwrap().flush()
+}
+
+// The next message of `subscription`, `None` once `deadline` has passed or
+// the subscription has ended
+fn next<T>(subscription: &Subscription<T>, deadline: Option<Instant>) -> Option<T> {
+    match deadline {
+        Some(deadline) => {
+            let left = deadline.saturating_duration_since(Instant::now());
+            subscription.recv_timeout(left).ok()
+        }
+        None => subscription.recv().ok(),
     }
 }
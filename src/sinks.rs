@@ -0,0 +1,108 @@
+use crate::kucoin::MarketBook;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Levels of each side written per row
+const CSV_LEVELS: usize = 5;
+
+/// How often a `CsvRecorder` flushes unless set otherwise.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Records level 2 books as rows of CSV: the exchange timestamp in
+/// milliseconds since the epoch, the symbol, then the price and size of the
+/// best 5 bids and of the best 5 asks. Fields of missing levels, or of a
+/// missing timestamp, are left empty.
+///
+/// The writer is flushed once the flush interval, one second unless set,
+/// has passed since the last flush. This is checked as rows are recorded, so
+/// callers waiting on a quiet feed call `flush_if_due` once `flush_due` has
+/// passed to write out the last rows. The `BufWriter` of `create` also
+/// flushes once dropped.
+pub struct CsvRecorder<W: Write = BufWriter<File>> {
+    writer: W,
+    flush_interval: Duration,
+    last_flush: Instant,
+    // Whether rows were recorded since the last flush
+    unflushed: bool,
+}
+
+impl CsvRecorder {
+    /// Creates the file at `path`, truncating it if it exists, and writes the
+    /// header row.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        CsvRecorder::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> CsvRecorder<W> {
+    /// Records to `writer`, starting with the header row.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        write!(writer, "timestamp,symbol")?;
+        for side in ["bid", "ask"] {
+            for n in 1..=CSV_LEVELS {
+                write!(writer, ",{side}_price_{n},{side}_size_{n}")?;
+            }
+        }
+        writeln!(writer)?;
+
+        Ok(CsvRecorder {
+            writer,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            last_flush: Instant::now(),
+            unflushed: false,
+        })
+    }
+
+    /// Sets how long rows may stay buffered before they are written out.
+    /// Zero writes out every row as it is recorded.
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Writes a row for `book` of `symbol`, flushing if the flush interval
+    /// has passed since the last flush.
+    pub fn record(&mut self, symbol: &str, book: &MarketBook) -> io::Result<()> {
+        match book.exchange_timestamp() {
+            Some(timestamp) => write!(self.writer, "{timestamp},{symbol}")?,
+            None => write!(self.writer, ",{symbol}")?,
+        }
+        for levels in [book.bids(), book.asks()] {
+            for n in 0..CSV_LEVELS {
+                match levels.get(n) {
+                    Some(level) => write!(self.writer, ",{},{}", level.price, level.size)?,
+                    None => write!(self.writer, ",,")?,
+                }
+            }
+        }
+        writeln!(self.writer)?;
+        self.unflushed = true;
+
+        self.flush_if_due()
+    }
+
+    /// When buffered rows are due to be written out, `None` if there are
+    /// none.
+    pub fn flush_due(&self) -> Option<Instant> {
+        self.unflushed
+            .then(|| self.last_flush + self.flush_interval)
+    }
+
+    /// Flushes if the flush interval has passed since the last flush and
+    /// rows were recorded since.
+    pub fn flush_if_due(&mut self) -> io::Result<()> {
+        match self.flush_due() {
+            Some(due) if due <= Instant::now() => self.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes out every buffered row.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.unflushed = false;
+        self.writer.flush()
+    }
+}
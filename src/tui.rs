@@ -0,0 +1,148 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+use std::time::{Duration, Instant};
+use tgt_warmup::kucoin::{Level, MarketBook, Subscription};
+
+// Levels of each side shown on the ladder
+const LADDER_DEPTH: usize = 5;
+// How often the view is redrawn, at most
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+// Period over which the update rate is averaged
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// What is shown of one symbol
+struct SymbolView {
+    symbol: String,
+    subscription: Subscription,
+    book: Option<MarketBook>,
+    // Updates received since `window_start`
+    updates: u32,
+    window_start: Instant,
+    // Updates per second over the last full window
+    rate: f64,
+}
+
+impl SymbolView {
+    fn new(symbol: String, subscription: Subscription) -> Self {
+        SymbolView {
+            symbol,
+            subscription,
+            book: None,
+            updates: 0,
+            window_start: Instant::now(),
+            rate: 0.0,
+        }
+    }
+
+    // Takes in every book received since the last call
+    fn poll(&mut self, now: Instant) {
+        while let Ok(book) = self.subscription.try_recv() {
+            self.book = Some(book);
+            self.updates += 1;
+        }
+
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed >= RATE_WINDOW {
+            self.rate = self.updates as f64 / elapsed.as_secs_f64();
+            self.updates = 0;
+            self.window_start = now;
+        }
+    }
+}
+
+/// Shows the ladder, spread and update rate of every subscribed symbol
+/// until `q` or Esc is pressed, or until `deadline`, if any.
+pub fn run(
+    subscriptions: Vec<(String, Subscription)>,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    let mut views: Vec<SymbolView> = subscriptions
+        .into_iter()
+        .map(|(symbol, subscription)| SymbolView::new(symbol, subscription))
+        .collect();
+    views.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    ratatui::run(|terminal| show(terminal, &mut views, deadline))
+}
+
+fn show(
+    terminal: &mut DefaultTerminal,
+    views: &mut [SymbolView],
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    loop {
+        let now = Instant::now();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            return Ok(());
+        }
+
+        for view in views.iter_mut() {
+            view.poll(now);
+        }
+        terminal.draw(|frame| draw(frame, views))?;
+
+        if event::poll(REFRESH_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, views: &[SymbolView]) {
+    let columns = Layout::horizontal(vec![Constraint::Fill(1); views.len()]).split(frame.area());
+    for (view, area) in views.iter().zip(columns.iter()) {
+        frame.render_widget(ladder(view), *area);
+    }
+}
+
+// Asks above bids, best prices in the middle
+fn ladder(view: &SymbolView) -> Table<'static> {
+    let mut rows = Vec::with_capacity(2 * LADDER_DEPTH);
+    let mut footer = format!("{:.1} updates/s", view.rate);
+
+    if let Some(book) = &view.book {
+        let asks: Vec<Level> = book.asks_iter().take(LADDER_DEPTH).collect();
+        let bids = book.bids_iter().take(LADDER_DEPTH);
+        rows.extend(asks.iter().rev().map(|level| {
+            Row::new([
+                "".to_owned(),
+                level.price.to_string(),
+                level.size.to_string(),
+            ])
+            .style(Style::new().fg(Color::Red))
+        }));
+        rows.extend(bids.map(|level| {
+            Row::new([
+                level.size.to_string(),
+                level.price.to_string(),
+                "".to_owned(),
+            ])
+            .style(Style::new().fg(Color::Green))
+        }));
+
+        if let Some(spread) = book.spread() {
+            // Prices are floats, the spread may come out as 0.09999999999
+            let spread = format!("{spread:.8}");
+            let spread = spread.trim_end_matches('0').trim_end_matches('.');
+            footer = format!("spread {spread} | {footer}");
+        }
+    }
+
+    Table::new(rows, [Constraint::Fill(1); 3])
+        .header(Row::new(["Bid size", "Price", "Ask size"]))
+        .block(
+            Block::bordered()
+                .title(Line::from(view.symbol.clone()).centered())
+                .title_bottom(Line::from(footer).centered()),
+        )
+}